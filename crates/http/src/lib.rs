@@ -2,12 +2,21 @@
 
 extern crate alloc;
 
-use alloc::{boxed::Box, string::String};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{error::Error, fmt};
+use http_body_util::{BodyExt as _, Empty, LengthLimitError, Limited};
+use hyper::{StatusCode, Uri, body::Bytes, http::uri::InvalidUri};
+use hyper_rustls::{ConfigBuilderExt as _, HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::{
+	client::legacy::{Client as HyperClient, Error as HyperError, connect::HttpConnector},
+	rt::TokioExecutor,
+};
+use rustls::ClientConfig;
 use tracing::{info, instrument};
 use twilight_http::{Client, client::InteractionClient};
 use twilight_model::{
-	channel::message::Embed,
 	channel::message::MessageFlags,
+	channel::message::{Component, Embed},
 	http::attachment::Attachment,
 	id::{Id, marker::ApplicationMarker},
 };
@@ -16,13 +25,86 @@ pub type ApplicationId = Id<ApplicationMarker>;
 
 pub struct Http {
 	http: Client,
+	cdn: HyperClient<HttpsConnector<HttpConnector>, Empty<Bytes>>,
 }
 
 type TwilightHttpError<T> = Result<T, twilight_http::Error>;
 
+#[derive(Debug)]
+pub enum DownloadError {
+	Uri(InvalidUri),
+	Request(HyperError),
+	Status(StatusCode),
+	/// The body exceeded the size limit.
+	TooLarge(usize),
+	/// The connection failed while the body was being received.
+	Body(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for DownloadError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Uri(error) => write!(f, "invalid attachment url: {error}"),
+			Self::Request(error) => write!(f, "attachment request failed: {error}"),
+			Self::Status(status) => write!(f, "attachment request returned {status}"),
+			Self::TooLarge(limit) => write!(f, "attachment exceeds the limit of {limit} bytes"),
+			Self::Body(error) => write!(f, "attachment body failed: {error}"),
+		}
+	}
+}
+
+impl Error for DownloadError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Uri(error) => Some(error),
+			Self::Request(error) => Some(error),
+			Self::Body(error) => Some(error.as_ref()),
+			Self::Status(_) | Self::TooLarge(_) => None,
+		}
+	}
+}
+
 impl Http {
 	pub fn new(bot_token: String) -> Self {
-		Self { http: Client::new(bot_token) }
+		let tls = ClientConfig::builder()
+			.try_with_platform_verifier()
+			.expect("crypto provider must support the platform verifier")
+			.with_no_client_auth();
+		let connector =
+			HttpsConnectorBuilder::new().with_tls_config(tls).https_only().enable_http1().build();
+		let cdn = HyperClient::builder(TokioExecutor::new()).build(connector);
+		Self { http: Client::new(bot_token), cdn }
+	}
+
+	/// Downloads an attachment from the Discord CDN. The download is aborted as soon as the body
+	/// exceeds `limit` bytes, regardless of what the attachment metadata claims.
+	#[instrument(skip(self), level = "trace")]
+	pub async fn download_attachment(
+		&self,
+		url: &str,
+		limit: usize,
+	) -> Result<Vec<u8>, DownloadError> {
+		let uri = url.parse::<Uri>().map_err(DownloadError::Uri)?;
+		let response = self.cdn.get(uri).await.map_err(DownloadError::Request)?;
+
+		let status = response.status();
+		if !status.is_success() {
+			return Err(DownloadError::Status(status));
+		}
+
+		let body = Limited::new(response.into_body(), limit)
+			.collect()
+			.await
+			.map_err(|error| {
+				if error.is::<LengthLimitError>() {
+					DownloadError::TooLarge(limit)
+				} else {
+					DownloadError::Body(error)
+				}
+			})?
+			.to_bytes();
+		info!(size = body.len(), "attachment downloaded");
+		Ok(body.into())
 	}
 
 	pub fn interaction<'token>(
@@ -40,32 +122,54 @@ pub struct HttpInteraction<'http> {
 }
 
 impl HttpInteraction<'_> {
+	/// Replaces any components since the response is final.
 	#[instrument(skip(self), level = "trace")]
 	pub async fn update_response_with_embeds(
 		&self,
 		content: &str,
 		embeds: &[Embed],
+		components: &[Component],
 	) -> TwilightHttpError<()> {
 		let message = self
 			.http
 			.update_response(&self.interaction_token)
 			.content(Some(content))
 			.embeds(Some(embeds))
+			.components(Some(components))
 			.await?;
 		info!(?message, "response updated with embeds");
 		Ok(())
 	}
 
+	#[instrument(skip(self), level = "trace")]
+	pub async fn update_response_with_components(
+		&self,
+		content: &str,
+		components: &[Component],
+	) -> TwilightHttpError<()> {
+		let message = self
+			.http
+			.update_response(&self.interaction_token)
+			.content(Some(content))
+			.components(Some(components))
+			.await?;
+		info!(?message, "response updated with components");
+		Ok(())
+	}
+
 	#[instrument(skip(self), level = "trace")]
 	pub async fn replace_response_with_attachments(
 		&self,
+		content: Option<&str>,
 		attachments: &[Attachment],
+		components: &[Component],
 	) -> TwilightHttpError<()> {
 		let message = self
 			.http
 			.update_response(&self.interaction_token)
-			.content(None)
+			.content(content)
 			.embeds(None)
+			.components(Some(components))
 			.attachments(attachments)
 			.await?;
 		info!(?message, "response replaced with attachments");
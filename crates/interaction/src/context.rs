@@ -0,0 +1,49 @@
+use twilight_model::{
+	id::{
+		Id,
+		marker::{ChannelMarker, GuildMarker},
+	},
+	user::User,
+};
+
+/// Where and by whom a render was requested. Documents can read it through `sys.inputs`.
+#[derive(Debug)]
+pub struct Context {
+	/// The server nickname, falling back to the display name and then the username.
+	pub author: Box<str>,
+	/// [`None`] in direct messages.
+	pub guild: Option<Id<GuildMarker>>,
+	pub channel: Option<Id<ChannelMarker>>,
+	/// The language of the user's client (e.g., `en-US`).
+	pub locale: Option<Box<str>>,
+}
+
+impl Context {
+	pub fn new(
+		user: &User,
+		nickname: Option<String>,
+		guild: Option<Id<GuildMarker>>,
+		channel: Option<Id<ChannelMarker>>,
+		locale: Option<String>,
+	) -> Self {
+		let author =
+			nickname.or_else(|| user.global_name.clone()).unwrap_or_else(|| user.name.clone());
+		Self { author: author.into(), guild, channel, locale: locale.map(String::into_boxed_str) }
+	}
+
+	/// Unknown values are left out so that documents can fall back to their own defaults (e.g.,
+	/// `sys.inputs.at("guild", default: none)`).
+	pub fn inputs(&self) -> Vec<(String, String)> {
+		let mut inputs = vec![("author".into(), self.author.to_string())];
+		if let Some(guild) = self.guild {
+			inputs.push(("guild".into(), guild.to_string()));
+		}
+		if let Some(channel) = self.channel {
+			inputs.push(("channel".into(), channel.to_string()));
+		}
+		if let Some(locale) = &self.locale {
+			inputs.push(("locale".into(), locale.to_string()));
+		}
+		inputs
+	}
+}
@@ -0,0 +1,116 @@
+use core::{error::Error, fmt, time::Duration};
+use std::{borrow::Cow, io};
+use twilight_model::application::{command::CommandType, interaction::InteractionType};
+use typscord_http::DownloadError;
+use typscord_protocol::ProtocolError;
+
+/// Everything that can go wrong while handling an interaction. The [`Display`](fmt::Display)
+/// implementation doubles as the message shown to the user.
+#[derive(Debug)]
+pub enum InteractionError {
+	/// The request was turned down with a user-facing explanation (e.g., an attachment exceeds the
+	/// size limit). This is not a bug.
+	Rejected(Cow<'static, str>),
+	UnknownInteraction(InteractionType),
+	UnknownCommand(CommandType, Box<str>),
+	/// The custom ID of a modal or message component is not recognized.
+	UnknownCustomId(Box<str>),
+	/// Discord left out a field that should always be present.
+	MissingField(&'static str),
+	/// Discord sent a field with an unexpected value.
+	InvalidField(&'static str),
+	Download(Box<str>, DownloadError),
+	/// The worker could not be spawned or fed.
+	Worker(io::Error),
+	/// The user (or everyone) renders too often. Retrying after the given duration succeeds.
+	RateLimited(Duration),
+	/// The render queue is full.
+	Overloaded,
+	/// The requester cancelled the render.
+	Cancelled,
+	/// The render could not start early enough to finish before the interaction token expires.
+	Expired,
+	/// The worker did not finish within the given number of milliseconds.
+	Timeout(u128),
+	/// The worker was killed for exceeding its memory or CPU time limit.
+	ResourceLimit(ProtocolError),
+	/// The worker exited without a (valid) response.
+	Crashed(ProtocolError),
+	Discord(twilight_http::Error),
+}
+
+impl fmt::Display for InteractionError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Rejected(reason) => f.write_str(reason),
+			Self::UnknownInteraction(_) | Self::UnknownCommand(..) | Self::UnknownCustomId(_) => {
+				f.write_str("This interaction is not supported. The command may be outdated.")
+			}
+			Self::MissingField(field) => {
+				write!(f, "Discord did not provide the {field}. Please try again.")
+			}
+			Self::InvalidField(field) => {
+				write!(f, "Discord provided an invalid {field}. Please try again.")
+			}
+			Self::Download(filename, _) => {
+				write!(f, "Failed to download `{filename}`. Please try again.")
+			}
+			Self::RateLimited(wait) => {
+				// Round up so that retrying after the given time always works
+				let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+				write!(f, "Slow down! Please retry in **{seconds}s**.")
+			}
+			Self::Overloaded => f.write_str(
+				"Too many renders are queued right now. Please retry in a few seconds.",
+			),
+			Self::Cancelled => f.write_str("Cancelled."),
+			Self::Expired => f.write_str(
+				"The queue was too long to finish your render within Discord's 15-minute limit. Please try again later.",
+			),
+			Self::Worker(_) => {
+				f.write_str("The Typst renderer is unavailable. Please try again later.")
+			}
+			Self::Timeout(millis) => write!(
+				f,
+				"Compilation timed out after **{millis}ms**. Check your code for infinite loops and expensive operations."
+			),
+			Self::ResourceLimit(_) => f.write_str(
+				"Resource limit exceeded. The document uses too much memory or CPU time.",
+			),
+			Self::Crashed(_) => {
+				f.write_str("The Typst renderer crashed. Please try again with simpler input.")
+			}
+			Self::Discord(_) => f.write_str("Failed to respond through Discord. Please try again."),
+		}
+	}
+}
+
+impl Error for InteractionError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Download(_, error) => Some(error),
+			Self::Worker(error) => Some(error),
+			Self::ResourceLimit(error) | Self::Crashed(error) => Some(error),
+			Self::Discord(error) => Some(error),
+			_ => None,
+		}
+	}
+}
+
+impl From<twilight_http::Error> for InteractionError {
+	fn from(error: twilight_http::Error) -> Self {
+		Self::Discord(error)
+	}
+}
+
+impl From<&'static str> for InteractionError {
+	fn from(reason: &'static str) -> Self {
+		Self::Rejected(reason.into())
+	}
+}
+
+impl From<String> for InteractionError {
+	fn from(reason: String) -> Self {
+		Self::Rejected(reason.into())
+	}
+}
@@ -0,0 +1,37 @@
+use crate::error::InteractionError;
+use typscord_protocol::Format;
+
+pub const fn label(format: Format) -> &'static str {
+	match format {
+		Format::WebP => "WebP",
+		Format::Png => "PNG",
+		Format::Svg => "SVG",
+		Format::Pdf => "PDF",
+	}
+}
+
+pub const fn description(format: Format) -> &'static str {
+	match format {
+		Format::WebP => "Compact image that previews inline.",
+		Format::Png => "Lossless image that previews inline.",
+		Format::Svg => "Scalable vector graphics for embedding elsewhere.",
+		Format::Pdf => "Downloadable document with selectable text.",
+	}
+}
+
+/// PDF output is only compiled in with the `pdf` feature, so it is not offered otherwise.
+pub const fn is_available(format: Format) -> bool {
+	!matches!(format, Format::Pdf) || cfg!(feature = "pdf")
+}
+
+/// Parses the extension chosen by the user, turning down formats the server cannot produce before
+/// any worker is spawned.
+pub fn parse(extension: &str) -> Result<Format, InteractionError> {
+	match Format::from_extension(extension) {
+		Some(format) if is_available(format) => Ok(format),
+		Some(format) => {
+			Err(format!("{} output is not available on this server.", label(format)).into())
+		}
+		None => Err(InteractionError::InvalidField("output format")),
+	}
+}
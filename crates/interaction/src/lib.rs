@@ -1,18 +1,38 @@
-mod buffer;
+mod context;
+mod error;
+mod format;
+mod limit;
+mod message;
+mod pool;
+mod preferences;
+mod queue;
+mod resolution;
+mod store;
+mod theme;
+mod upload;
 
+use context::Context;
 use core::time::Duration;
-use std::{io, path::Path, process::Stdio, sync::Arc, time::Instant};
+use limit::RateLimiter;
+use preferences::Preferences;
+use queue::{Queue, Ticket};
+use resolution::Resolution;
+use std::{os::unix::process::ExitStatusExt as _, sync::Arc};
+use store::{InteractionId, Store};
+use theme::Theme;
 use tokio::{
-	io::{AsyncBufRead, AsyncReadExt as _, AsyncWriteExt as _, BufReader},
-	process::{Child, Command},
+	process::{Child, ChildStdout},
+	sync::{Notify, OwnedSemaphorePermit},
+	time::Instant,
 };
 use tracing::{error, info, instrument, trace};
 use twilight_model::{
 	application::{
 		command::CommandType,
 		interaction::{
-			Interaction, InteractionData, InteractionType,
-			application_command::CommandData,
+			InteractionData, InteractionType,
+			application_command::{CommandData, CommandDataOption, CommandOptionValue},
+			message_component::MessageComponentInteractionData,
 			modal::{
 				ModalInteractionComponent, ModalInteractionData, ModalInteractionLabel,
 				ModalInteractionStringSelect, ModalInteractionTextInput,
@@ -31,26 +51,199 @@ use twilight_model::{
 		attachment::Attachment,
 		interaction::{InteractionResponseData, InteractionResponseType},
 	},
+	id::{Id, marker::UserMarker},
 };
-use typscord_http::{ApplicationId, Http};
+use typscord_http::{ApplicationId, DownloadError, Http, HttpInteraction};
+use typscord_protocol::{
+	Diagnostic, Format, Location, Pages, ProtocolError, Render, Request, Response, Timing, Trace,
+};
+use upload::{MAX_UPLOAD_SIZE, Upload};
+
+pub use error::InteractionError;
+pub use pool::WorkerPool;
+pub use twilight_model::{
+	application::interaction::Interaction, http::interaction::InteractionResponse,
+};
+
+static TYPST_PREAMBLE: &str = include_str!("preamble.typ");
+
+/// Opens the display math that the `/math` input is wrapped in. The input starts on a fresh line
+/// so that diagnostics point at the formula itself.
+static MATH_PREAMBLE: &str = include_str!("math.typ");
+
+/// Discord limits text inputs to 4000 characters.
+const MAX_CODE_LENGTH: usize = 4000;
+
+/// Number of commands whose options are remembered while their modal is open.
+const PENDING_CAPACITY: usize = 256;
+
+/// Number of renders that can still be edited.
+const DRAFT_CAPACITY: usize = 256;
+
+/// Number of users whose preferences are remembered.
+const PREFERENCE_CAPACITY: usize = 4096;
+
+/// Bounds of the maximum page width (in points) that users may choose.
+const MIN_WIDTH: u16 = 100;
+const MAX_WIDTH: u16 = 1000;
+const DEFAULT_WIDTH: u16 = 460;
+
+/// Number of renders that may wait for a free worker before new ones are turned down.
+const MAX_QUEUE_LENGTH: usize = 32;
+
+/// Discord invalidates interaction tokens after 15 minutes.
+const INTERACTION_TOKEN_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// How long a failed worker may take to exit on its own before it is killed.
+const EXIT_GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+/// Time reserved for downloading the attachments and uploading the render.
+const TRANSFER_MARGIN: Duration = Duration::from_secs(60);
 
-pub use twilight_model::http::interaction::InteractionResponse;
+type UserId = Id<UserMarker>;
+
+/// How the code is rendered, regardless of where it came from.
+#[derive(Clone, Copy, Debug)]
+struct Options {
+	format: Format,
+	/// Whether to attach each page separately instead of merging them into one file.
+	split_pages: bool,
+	spoiler: bool,
+	/// Whether the code is a formula to be wrapped in display math.
+	math: bool,
+	preferences: Preferences,
+	resolution: Resolution,
+	/// Maximum width of the page in points.
+	width: u16,
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			format: Format::default(),
+			split_pages: false,
+			spoiler: false,
+			math: false,
+			preferences: Preferences::default(),
+			resolution: Resolution::default(),
+			width: DEFAULT_WIDTH,
+		}
+	}
+}
+
+/// Everything that the worker needs for a single render.
+#[derive(Debug)]
+struct Job {
+	/// Empty when rendering the entry point of a project.
+	code: Box<str>,
+	uploads: Box<[Upload]>,
+	/// The project file to compile instead of the code.
+	entry: Option<Box<str>>,
+	options: Options,
+	context: Context,
+	/// Shown above the rendered output (e.g., a link to the message that was rendered).
+	reference: Option<Box<str>>,
+	/// The interaction that created the render. Edits keep referring to the original one.
+	key: InteractionId,
+	author: UserId,
+	/// Whether the job edits an existing render instead of responding with a new message.
+	update: bool,
+}
+
+/// Everything needed to (re)open the modal of a render.
+#[derive(Clone)]
+struct Draft {
+	/// Only the author may edit the render.
+	author: UserId,
+	code: Box<str>,
+	uploads: Box<[Upload]>,
+	options: Options,
+	reference: Option<Box<str>>,
+}
+
+/// A fresh render that is queued or in progress.
+#[derive(Clone)]
+struct Running {
+	/// Only the author may cancel the render.
+	author: UserId,
+	cancel: Arc<Notify>,
+}
+
+/// The successful result of a worker process.
+struct Output {
+	/// Empty if the compilation failed.
+	render: Option<Render>,
+	timing: Timing,
+	embeds: Vec<Embed>,
+}
 
 pub struct InteractionHandler {
 	compilation_timeout: Duration,
-	exe_path: Box<Path>,
+	workers: WorkerPool,
 	http: Http,
+	/// The options of a slash command cannot be read back from the modal submission, so they are
+	/// kept until the modal is submitted.
+	pending: Store<Draft>,
+	drafts: Store<Draft>,
+	/// Renders that can still be cancelled.
+	running: Store<Running>,
+	preferences: Store<Preferences, UserId>,
+	/// Listed by the `/fonts` command.
+	font_families: Box<[Box<str>]>,
+	limiter: RateLimiter,
+	/// Renders wait here for a free worker and hold a permit until the worker is done.
+	queue: Arc<Queue>,
 }
 
 impl InteractionHandler {
-	pub fn new(compilation_timeout: Duration, exe_path: Box<Path>, bot_token: String) -> Self {
-		Self { compilation_timeout, exe_path, http: Http::new(bot_token) }
+	pub fn new(
+		compilation_timeout: Duration,
+		workers: WorkerPool,
+		bot_token: String,
+		font_families: Vec<String>,
+		max_concurrent_renders: usize,
+	) -> Self {
+		Self {
+			compilation_timeout,
+			workers,
+			http: Http::new(bot_token),
+			font_families: font_families.into_iter().map(String::into_boxed_str).collect(),
+			limiter: RateLimiter::new(),
+			queue: Arc::new(Queue::new(max_concurrent_renders, MAX_QUEUE_LENGTH)),
+			pending: Store::new(PENDING_CAPACITY),
+			drafts: Store::new(DRAFT_CAPACITY),
+			running: Store::new(MAX_QUEUE_LENGTH + max_concurrent_renders),
+			preferences: Store::new(PREFERENCE_CAPACITY),
+		}
 	}
 
+	/// Failures are reported back to the user as an ephemeral message instead of panicking.
 	#[must_use]
 	#[instrument(skip_all)]
 	pub fn handle(self: Arc<Self>, interaction: Interaction) -> InteractionResponse {
-		match interaction {
+		match self.try_handle(interaction) {
+			Ok(response) => response,
+			Err(error) => {
+				if matches!(
+					error,
+					InteractionError::Rejected(_)
+						| InteractionError::RateLimited(_)
+						| InteractionError::Overloaded
+				) {
+					info!(%error, "interaction rejected");
+				} else {
+					error!(?error, "failed to handle interaction");
+				}
+				ephemeral_message(error.to_string())
+			}
+		}
+	}
+
+	fn try_handle(
+		self: Arc<Self>,
+		interaction: Interaction,
+	) -> Result<InteractionResponse, InteractionError> {
+		Ok(match interaction {
 			Interaction { id, kind: InteractionType::Ping, .. } => {
 				info!(interaction_id = ?id, "received ping");
 				InteractionResponse { kind: InteractionResponseType::Pong, data: None }
@@ -61,22 +254,26 @@ impl InteractionHandler {
 				member,
 				guild_id,
 				channel,
+				application_id,
+				token,
+				locale,
 				kind: InteractionType::ApplicationCommand,
 				data: Some(InteractionData::ApplicationCommand(cmd)),
 				..
 			} => {
-				let user = member.and_then(|m| m.user).or(user).expect("user must be present");
+				let nickname = member.as_ref().and_then(|m| m.nick.clone());
+				let user = member
+					.and_then(|m| m.user)
+					.or(user)
+					.ok_or(InteractionError::MissingField("user"))?;
 				let channel_id = channel.map(|c| c.id);
+				let context = Context::new(&user, nickname, guild_id, channel_id, locale);
 				info!(interaction_id = ?id, user_id = ?user.id, ?guild_id, ?channel_id, "received application command");
 
-				let CommandData { kind, name, .. } = *cmd;
-				assert_eq!(kind, CommandType::ChatInput);
-
-				// Just some constant strings that will be useful for commands later.
-				const CODE_PLACEHOLDER: &str = "Hello, Typst!";
+				let CommandData { kind, name, options, resolved, target_id, .. } = *cmd;
 
-				match name.as_str() {
-					"help" => InteractionResponse {
+				match (kind, name.as_str()) {
+					(CommandType::ChatInput, "help") => InteractionResponse {
 						kind: InteractionResponseType::ChannelMessageWithSource,
 						data: Some(InteractionResponseData {
 							flags: Some(MessageFlags::EPHEMERAL),
@@ -120,32 +317,56 @@ impl InteractionHandler {
 								}),
 								color: Some(0x7ad5d5),
 								description: Some(
-									"The `/typst` command is the main entry point to using Typscord. The command opens a modal that allows you to write Typst code in Discord. Upon submission, the Typst code will be rendered as an image in Discord. However, there are some limitations about the generated image.".into(),
+									"The `/typst` command is the main entry point to using Typscord. The command opens a modal that allows you to write Typst code in Discord. Upon submission, the Typst code will be rendered as an image (or as an SVG or PDF file) in Discord. For quick one-liners, the `code` option skips the modal altogether. However, there are some limitations about the generated output.".into(),
 								),
 								fields: vec![
 									EmbedField {
-										name: "You may want to copy your Typst code before hitting submit.".into(),
-										value: "Just in case the bot fails to respond, you can always paste your code back into the modal. It's not ideal, but it's the best we have for now.".into(),
+										name: "Renders can be edited with the **Edit** button.".into(),
+										value: "The button reopens the modal with your previous code, and only you can use it. Still, you may want to copy your code before hitting submit just in case the bot fails to respond.".into(),
+										inline: false,
+									},
+									EmbedField {
+										name: "Only approved third-party packages are supported.".into(),
+										value: "For hosting and security reasons, packages are served from an offline store. Only the packages (and versions) approved by the host can be imported.".into(),
 										inline: false,
 									},
 									EmbedField {
-										name: "Third-party packages are unsupported.".into(),
-										value: "This is mostly for hosting and security reasons.".into(),
+										name: "Images must be attached to the `/typst` command.".into(),
+										value: format!(
+											"Attach up to four files ({} MiB each, {} MiB in total) through the `file` options of the command. The Typst code can then reference them by filename (e.g., `#image(\"diagram.png\")`). Attached `.zip` archives are extracted as a project. Set the `entry` option to compile a project file (e.g., `main.typ`) instead of opening the editor.",
+											MAX_UPLOAD_SIZE >> 20,
+											upload::MAX_TOTAL_UPLOAD_SIZE >> 20,
+										),
 										inline: false,
 									},
 									EmbedField {
-										name: "Image uploads are unsupported.".into(),
-										value: "This requires that I allow users to add image attachments (which they can reference as relative paths in the Typst code), but I haven't gotten to that yet.".into(),
+										name: "Renders can match your Discord theme and timezone.".into(),
+										value: "Set the `theme` option to `dark` (or `transparent`) and the `timezone` option to your UTC offset (e.g., `+08:00`) once, and your later renders will use them, too (until the bot restarts). The date of `datetime.today()` is that of the original command, even when editing.".into(),
 										inline: false,
 									},
 									EmbedField {
-										name: "Only stock Typst fonts are supported.".into(),
-										value: "Note that some emojis will fail to load.".into(),
+										name: "Documents know who requested them.".into(),
+										value: "`sys.inputs` has the `author`, `guild`, `channel`, `locale`, and `theme` of the render (e.g., `#sys.inputs.at(\"author\")`).".into(),
 										inline: false,
 									},
 									EmbedField {
-										name: "The rendered image will be as wide as possible.".into(),
-										value: "This unfortunately disables automatic line breaks. To opt into automatic line breaks, you have to wrap the code in a `#box` with the desired `width` yourself.".into(),
+										name: "Equations have their own `/math` command.".into(),
+										value: "The input is wrapped in display math (i.e., `$ ... $`) and rendered at a larger size with tight margins. Like `/typst`, the `code` option skips the modal.".into(),
+										inline: false,
+									},
+									EmbedField {
+										name: "Code that is already in chat can be rendered, too.".into(),
+										value: "Right-click (or long-press) a message and select **Apps → Render Typst**. If the message has ```` ```typ ```` or ```` ```typst ```` code blocks, only those are rendered.".into(),
+										inline: false,
+									},
+									EmbedField {
+										name: "Only the fonts installed on the server are supported.".into(),
+										value: "Run `/fonts` to list them. Emojis only work if the server has an emoji font.".into(),
+										inline: false,
+									},
+									EmbedField {
+										name: "Long lines wrap at 460pt by default.".into(),
+										value: "Set the `width` option to change where lines wrap, and the `resolution` option to make images sharper (or smaller).".into(),
 										inline: false,
 									},
 									EmbedField {
@@ -171,7 +392,7 @@ impl InteractionHandler {
 							..Default::default()
 						}),
 					},
-					"info" => InteractionResponse {
+					(CommandType::ChatInput, "info") => InteractionResponse {
 						kind: InteractionResponseType::ChannelMessageWithSource,
 						data: Some(InteractionResponseData {
 							allowed_mentions: None,
@@ -247,74 +468,172 @@ impl InteractionHandler {
 							..Default::default()
 						}),
 					},
-					"typst" => InteractionResponse {
-						kind: InteractionResponseType::Modal,
-						data: Some(InteractionResponseData {
-							flags: Some(MessageFlags::IS_COMPONENTS_V2),
-							custom_id: Some("typst".into()),
-							title: Some("Render Typst Code".into()),
-							components: Some(vec![
-								Component::Label(Label {
-									id: None,
-									label: "Typst Code".into(),
-									description: Some(
-										"Third-party packages and images aren't supported yet. Long compilations will be aborted.".into(),
-									),
-									component: Box::new(Component::TextInput(TextInput {
-										id: None,
-										custom_id: "code".into(),
-										#[expect(deprecated, reason = "not actually used")]
-										label: None,
-										style: TextInputStyle::Paragraph,
-										max_length: Some(4000),
-										placeholder: Some(CODE_PLACEHOLDER.into()),
-										required: Some(true),
-										value: None,
-										min_length: None,
-									})),
-								}),
-								Component::Label(Label {
-									id: None,
-									label: "Mark as Spoiler?".into(),
-									description: Some(
-										"Whether to hide the rendered image behind a spoiler.".into(),
-									),
-									component: Box::new(Component::SelectMenu(SelectMenu {
-										id: None,
-										custom_id: "spoiler".into(),
-										kind: SelectMenuType::Text,
-										disabled: false,
-										options: Some(vec![
-											SelectMenuOption {
-												default: true,
-												description: None,
-												emoji: None,
-												label: "No".into(),
-												value: "no".into(),
-											},
-											SelectMenuOption {
-												default: false,
-												description: None,
-												emoji: None,
-												label: "Yes".into(),
-												value: "yes".into(),
-											},
-										]),
-										placeholder: None,
-										min_values: None,
-										max_values: None,
-										default_values: None,
-										channel_types: None,
-										required: None,
-									})),
-								}),
-							]),
-							..Default::default()
-						}),
-					},
-					name => {
-						error!(name, "unknown command");
-						unreachable!("unknown command");
+					(CommandType::ChatInput, "fonts") => {
+						let families = self
+							.font_families
+							.iter()
+							.map(|family| format!("`{family}`"))
+							.collect::<Vec<_>>()
+							.join(", ");
+						InteractionResponse {
+							kind: InteractionResponseType::ChannelMessageWithSource,
+							data: Some(InteractionResponseData {
+								flags: Some(MessageFlags::EPHEMERAL),
+								embeds: Some(vec![Embed {
+									author: None,
+									color: Some(0x7ad5d5),
+									description: Some(truncate(families, MAX_DESCRIPTION_LENGTH)),
+									fields: Vec::new(),
+									footer: Some(EmbedFooter {
+										text: "Use them with #set text(font: \"...\").".into(),
+										icon_url: None,
+										proxy_icon_url: None,
+									}),
+									image: None,
+									kind: "rich".into(),
+									provider: None,
+									thumbnail: None,
+									timestamp: None,
+									title: Some(format!(
+										"{} Available Font Families",
+										self.font_families.len()
+									)),
+									url: None,
+									video: None,
+								}]),
+								..Default::default()
+							}),
+						}
+					}
+					(CommandType::ChatInput, "typst" | "math") => {
+						let mut code = None;
+						let mut entry = None;
+						let mut theme = None;
+						let mut utc_offset = None;
+						let mut render_options = Options { math: name == "math", ..Options::default() };
+						for CommandDataOption { name, value } in &options {
+							match (name.as_str(), value) {
+								("code", CommandOptionValue::String(value)) => {
+									code = Some(Box::from(value.as_str()));
+								}
+								("entry", CommandOptionValue::String(value)) => {
+									entry = Some(Box::from(value.as_str()));
+								}
+								("format", CommandOptionValue::String(value)) => {
+									render_options.format = format::parse(value)?;
+								}
+								("pages", CommandOptionValue::String(value)) => {
+									render_options.split_pages = value == "split";
+								}
+								("spoiler", CommandOptionValue::Boolean(value)) => {
+									render_options.spoiler = *value;
+								}
+								("theme", CommandOptionValue::String(value)) => {
+									theme = Some(
+										Theme::from_value(value)
+											.ok_or(InteractionError::InvalidField("theme"))?,
+									);
+								}
+								("timezone", CommandOptionValue::String(value)) => {
+									utc_offset = Some(preferences::parse_utc_offset(value).ok_or_else(
+										|| format!("`{value}` is not a UTC offset. Try something like `+08:00` or `-03:30`."),
+									)?);
+								}
+								("resolution", CommandOptionValue::String(value)) => {
+									render_options.resolution = Resolution::from_value(value)
+										.ok_or(InteractionError::InvalidField("resolution"))?;
+								}
+								("width", &CommandOptionValue::Integer(value)) => {
+									// Discord enforces the bounds too, but the options are not trusted
+									render_options.width =
+										value.clamp(MIN_WIDTH.into(), MAX_WIDTH.into()) as u16;
+								}
+								_ => {}
+							}
+						}
+
+						let uploads = upload::collect(options, resolved)?;
+						render_options.preferences = self.preferences(user.id, theme, utc_offset);
+
+						// Projects are rendered directly since the modal code would be ignored anyway
+						if entry.is_some() && uploads.is_empty() {
+							return Err(
+								"The `entry` option requires at least one attached file.".into()
+							);
+						}
+
+						// Inline code skips the modal just like projects do
+						if entry.is_some() || code.is_some() {
+							return self.defer(
+								application_id,
+								token,
+								Job {
+									code: code.unwrap_or_default(),
+									uploads,
+									entry,
+									options: render_options,
+									context,
+									reference: None,
+									key: id,
+									author: user.id,
+									update: false,
+								},
+							);
+						}
+
+						// The modal finds the attachments and the options through its custom ID
+						let draft = Draft {
+							author: user.id,
+							code: Box::default(),
+							uploads,
+							options: render_options,
+							reference: None,
+						};
+						let response = typst_modal(format!("new:{id}"), &draft);
+						self.pending.insert(id, draft);
+						response
+					}
+					(CommandType::Message, "Render Typst") => {
+						let target_id =
+							target_id.ok_or(InteractionError::MissingField("target message"))?;
+						let message = resolved
+							.and_then(|mut resolved| resolved.messages.remove(&target_id.cast()))
+							.ok_or(InteractionError::MissingField("message"))?;
+
+						let code = message::extract_code(&message.content);
+						if code.trim().is_empty() {
+							return Err("The message does not have any text to render.".into());
+						}
+
+						// The response links back to the message as if it were a reply
+						let guild = guild_id.map_or_else(|| "@me".into(), |id| id.to_string());
+						let reference = format!(
+							"Rendered from https://discord.com/channels/{guild}/{}/{}",
+							message.channel_id, message.id
+						);
+						let options = Options {
+							preferences: self.preferences(user.id, None, None),
+							..Options::default()
+						};
+
+						self.defer(
+							application_id,
+							token,
+							Job {
+								code: code.into_boxed_str(),
+								uploads: Box::default(),
+								entry: None,
+								options,
+								context,
+								reference: Some(reference.into_boxed_str()),
+								key: id,
+								author: user.id,
+								update: false,
+							},
+						)?
+					}
+					(kind, name) => {
+						return Err(InteractionError::UnknownCommand(kind, name.into()));
 					}
 				}
 			}
@@ -327,20 +646,43 @@ impl InteractionHandler {
 				channel,
 				application_id,
 				token,
+				locale,
 				data: Some(InteractionData::ModalSubmit(modal_data)),
 				..
 			} => {
 				let ModalInteractionData { custom_id, components, .. } = *modal_data;
 
-				let user = member.and_then(|m| m.user).or(user).expect("user must be present");
+				let nickname = member.as_ref().and_then(|m| m.nick.clone());
+				let user = member
+					.and_then(|m| m.user)
+					.or(user)
+					.ok_or(InteractionError::MissingField("user"))?;
 				let channel_id = channel.map(|c| c.id);
+				let context = Context::new(&user, nickname, guild_id, channel_id, locale);
 				info!(interaction_id = ?id, user_id = ?user.id, ?guild_id, ?channel_id, "received modal submit");
 
-				assert_eq!(custom_id, "typst");
+				// Edits refer to the original render so that its message can be updated
+				let (draft, key, update) = match custom_id.split_once(':') {
+					Some(("new", key)) => {
+						let key =
+							key.parse().or(Err(InteractionError::InvalidField("modal ID")))?;
+						let draft = self
+							.pending
+							.take(key)
+							.ok_or("This modal has expired. Please run the command again.")?;
+						(draft, id, false)
+					}
+					Some(("edit", key)) => {
+						let key =
+							key.parse().or(Err(InteractionError::InvalidField("modal ID")))?;
+						(self.draft(key, user.id)?, key, true)
+					}
+					_ => return Err(InteractionError::UnknownCustomId(custom_id.into())),
+				};
+				let Draft { uploads, mut options, reference, .. } = draft;
 
-				// Extract code from Label > TextInput and spoiler from Label > StringSelect
+				// Extract code from Label > TextInput and the rest from Label > StringSelect
 				let mut code: Option<String> = None;
-				let mut spoiler = false;
 
 				for component in components {
 					let ModalInteractionLabel { component: inner, .. } = match component {
@@ -361,169 +703,478 @@ impl InteractionHandler {
 							values,
 							..
 						}) if custom_id == "spoiler" => {
-							spoiler = values.first().is_some_and(|v| v == "yes");
+							options.spoiler = values.first().is_some_and(|v| v == "yes");
+						}
+						ModalInteractionComponent::StringSelect(ModalInteractionStringSelect {
+							custom_id,
+							values,
+							..
+						}) if custom_id == "pages" => {
+							options.split_pages = values.first().is_some_and(|v| v == "split");
+						}
+						ModalInteractionComponent::StringSelect(ModalInteractionStringSelect {
+							custom_id,
+							values,
+							..
+						}) if custom_id == "format" => {
+							if let Some(value) = values.first() {
+								options.format = format::parse(value)?;
+							}
 						}
 						_ => {}
 					}
 				}
 
-				let value = code.expect("code input must be present");
+				let code = code.ok_or(InteractionError::MissingField("code"))?;
 
-				static TYPST_PREAMBLE: &str = include_str!("preamble.typ");
-				let mut content = TYPST_PREAMBLE.to_owned();
-				content.push_str(&value);
-
-				let token = token.into_boxed_str();
-				let handle = tokio::spawn(self.subprocess(
+				self.defer(
 					application_id,
 					token,
-					content.into_boxed_str(),
-					spoiler,
-				));
-				trace!(?handle, "spawned subprocess");
-
-				InteractionResponse {
-					kind: InteractionResponseType::DeferredChannelMessageWithSource,
-					data: None,
+					Job {
+						code: code.into_boxed_str(),
+						uploads,
+						entry: None,
+						options,
+						context,
+						reference,
+						key,
+						author: user.id,
+						update,
+					},
+				)?
+			}
+			Interaction {
+				kind: InteractionType::MessageComponent,
+				id,
+				user,
+				member,
+				guild_id,
+				channel,
+				data: Some(InteractionData::MessageComponent(component_data)),
+				..
+			} => {
+				let MessageComponentInteractionData { custom_id, .. } = *component_data;
+
+				let user = member
+					.and_then(|m| m.user)
+					.or(user)
+					.ok_or(InteractionError::MissingField("user"))?;
+				let channel_id = channel.map(|c| c.id);
+				info!(interaction_id = ?id, user_id = ?user.id, ?guild_id, ?channel_id, custom_id, "received message component");
+
+				let Some((action, key)) = custom_id.split_once(':') else {
+					return Err(InteractionError::UnknownCustomId(custom_id.into()));
+				};
+				let key = key.parse().or(Err(InteractionError::InvalidField("button ID")))?;
+				match action {
+					"edit" => {
+						let draft = self.draft(key, user.id)?;
+						typst_modal(custom_id, &draft)
+					}
+					"cancel" => {
+						self.cancel(key, user.id)?;
+						// The render itself replaces the message once it has stopped
+						InteractionResponse {
+							kind: InteractionResponseType::DeferredUpdateMessage,
+							data: None,
+						}
+					}
+					_ => return Err(InteractionError::UnknownCustomId(custom_id.into())),
 				}
 			}
-			_ => unreachable!("unknown interaction"),
+			Interaction { kind, .. } => return Err(InteractionError::UnknownInteraction(kind)),
+		})
+	}
+
+	/// Looks up the draft of a previous render on behalf of the user. The error is a user-facing
+	/// explanation of why the render cannot be edited.
+	fn draft(&self, key: InteractionId, user: UserId) -> Result<Draft, &'static str> {
+		let draft = self
+			.drafts
+			.get(key)
+			.ok_or("This render can no longer be edited. Please run the command again.")?;
+
+		if draft.author != user {
+			return Err("Only the author of this render can edit it.");
+		}
+
+		if draft.uploads.iter().any(Upload::is_expired) {
+			return Err(upload::EXPIRED_UPLOADS);
+		}
+
+		Ok(draft)
+	}
+
+	/// Stops a running render on behalf of the user. The error is a user-facing explanation of why
+	/// the render cannot be cancelled.
+	fn cancel(&self, key: InteractionId, user: UserId) -> Result<(), &'static str> {
+		let Running { author, cancel } =
+			self.running.get(key).ok_or("This render has already finished.")?;
+		if author != user {
+			return Err("Only the author of this render can cancel it.");
+		}
+
+		// Stored until the render is ready to stop if it is not waiting for it yet
+		cancel.notify_one();
+		Ok(())
+	}
+
+	/// Chosen preferences are remembered for the later renders of the user.
+	fn preferences(
+		&self,
+		user: UserId,
+		theme: Option<Theme>,
+		utc_offset: Option<i32>,
+	) -> Preferences {
+		let mut preferences = self.preferences.get(user).unwrap_or_default();
+		if theme.is_none() && utc_offset.is_none() {
+			return preferences;
+		}
+
+		if let Some(theme) = theme {
+			preferences.theme = theme;
+		}
+		if let Some(utc_offset) = utc_offset {
+			preferences.utc_offset = utc_offset;
+		}
+		self.preferences.insert(user, preferences);
+		preferences
+	}
+
+	/// Renders the job in the background while Discord shows its status. Renders beyond the
+	/// rate limits or the queue length are turned down before anything is deferred.
+	fn defer(
+		self: Arc<Self>,
+		application_id: ApplicationId,
+		token: String,
+		job: Job,
+	) -> Result<InteractionResponse, InteractionError> {
+		let ticket = self.queue.enqueue().ok_or(InteractionError::Overloaded)?;
+		self.limiter.acquire(job.author).map_err(InteractionError::RateLimited)?;
+
+		// Edits keep showing the previous render until the new one is ready. Fresh renders answer
+		// with their status right away so that the Cancel button does not depend on a later edit.
+		let position = if job.update { None } else { ticket.position() };
+		let response = if job.update {
+			InteractionResponse { kind: InteractionResponseType::DeferredUpdateMessage, data: None }
+		} else {
+			InteractionResponse {
+				kind: InteractionResponseType::ChannelMessageWithSource,
+				data: Some(InteractionResponseData {
+					content: Some(position.map_or_else(|| "Rendering…".into(), queued_status)),
+					components: Some(vec![cancel_button(job.key)]),
+					..Default::default()
+				}),
+			}
+		};
+
+		// Edits keep the buttons of the previous render, so only fresh renders can be cancelled
+		let cancel = Arc::new(Notify::new());
+		if !job.update {
+			let running = Running { author: job.author, cancel: Arc::clone(&cancel) };
+			self.running.insert(job.key, running);
 		}
+
+		let token = token.into_boxed_str();
+		let handle =
+			tokio::spawn(self.subprocess(application_id, token, job, ticket, position, cancel));
+		trace!(?handle, "spawned subprocess");
+
+		Ok(response)
 	}
 
-	#[instrument(skip(self))]
+	#[instrument(skip(self, ticket, cancel))]
 	async fn subprocess(
 		self: Arc<Self>,
 		application_id: ApplicationId,
 		token: Box<str>,
-		value: Box<str>,
-		spoiler: bool,
+		job: Job,
+		ticket: Ticket,
+		position: Option<usize>,
+		cancel: Arc<Notify>,
 	) {
-		let mut command = Command::new(self.exe_path.as_os_str())
-			.arg("worker")
-			.stdin(Stdio::piped())
-			.stdout(Stdio::piped())
-			.spawn()
-			.expect("worker process must be spawned");
-
-		// TODO: attachment_size_limit
-
-		command
-			.stdin
-			.take()
-			.expect("stdin must have been piped")
-			.write_all(value.as_bytes())
+		let Job { key, update, .. } = job;
+		let reference = job.reference.clone();
+		let http = self.http.interaction(application_id, token);
+		let result = self.run(&http, job, ticket, position, &cancel).await;
+		if !update {
+			self.running.take(key);
+		}
+		let Err(error) = result else {
+			return;
+		};
+
+		if matches!(error, InteractionError::Cancelled) {
+			info!("render cancelled");
+		} else {
+			error!(?error, "failed to render job");
+		}
+
+		// Fresh renders must replace the status message, whereas edits keep the previous render
+		let content = error.to_string();
+		let result = if update {
+			http.create_ephemeral_followup_with_embeds(&content, &[]).await
+		} else {
+			let content = with_reference(reference.as_deref(), &content);
+			http.update_response_with_embeds(&content, &[], &[]).await
+		};
+		if let Err(error) = result {
+			error!(?error, "failed to report error to discord");
+		}
+	}
+
+	/// Waits in the queue while keeping the message up to date with the position, starting from the
+	/// one that the interaction was answered with. Edits keep showing the previous render instead.
+	/// Waiting is given up once the render could no longer finish before the interaction token
+	/// expires.
+	async fn wait_for_turn(
+		&self,
+		http: &HttpInteraction<'_>,
+		mut ticket: Ticket,
+		mut reported: Option<usize>,
+		key: InteractionId,
+		update: bool,
+	) -> Result<OwnedSemaphorePermit, InteractionError> {
+		let deadline = Instant::now()
+			+ INTERACTION_TOKEN_LIFETIME.saturating_sub(self.compilation_timeout + TRANSFER_MARGIN);
+		let turn = async {
+			loop {
+				if let Some(position) = ticket.position()
+					&& !update && reported != Some(position)
+				{
+					reported = Some(position);
+					report_status(http, &queued_status(position), key).await;
+				}
+				if ticket.is_next() {
+					let permit = ticket.admit().await;
+					// Renders that did not wait already show this status
+					if reported.is_some() {
+						report_status(http, "Rendering…", key).await;
+					}
+					return permit;
+				}
+				ticket.advanced().await;
+			}
+		};
+		tokio::time::timeout_at(deadline, turn).await.or(Err(InteractionError::Expired))
+	}
+
+	async fn run(
+		&self,
+		http: &HttpInteraction<'_>,
+		Job { code, uploads, entry, options, context, reference, key, author, update }: Job,
+		ticket: Ticket,
+		position: Option<usize>,
+		cancel: &Notify,
+	) -> Result<(), InteractionError> {
+		// Projects cannot be edited since their code lives in the attachments
+		let draft = (entry.is_none() && code.chars().count() <= MAX_CODE_LENGTH).then(|| Draft {
+			author,
+			code: code.clone(),
+			uploads: uploads.clone(),
+			options,
+			reference: reference.clone(),
+		});
+		let Options {
+			format,
+			split_pages,
+			spoiler,
+			math,
+			preferences: Preferences { theme, utc_offset },
+			resolution,
+			width,
+		} = options;
+
+		// The render may be cancelled at any point until the worker has its response
+		let prepare = async {
+			let permit = self.wait_for_turn(http, ticket, position, key, update).await?;
+			// Discord may revoke the URLs of a draft before the expiry that they claim
+			let files =
+				upload::download(&self.http, uploads).await.map_err(|error| match error {
+					InteractionError::Download(_, DownloadError::Status(status))
+						if update && matches!(status.as_u16(), 403 | 404) =>
+					{
+						upload::EXPIRED_UPLOADS.into()
+					}
+					error => error,
+				})?;
+			Ok::<_, InteractionError>((permit, files))
+		};
+		let (_permit, files) = tokio::select! {
+			prepared = prepare => prepared?,
+			() = cancel.notified() => return Err(InteractionError::Cancelled),
+		};
+
+		let mut inputs = context.inputs();
+		inputs.push(("theme".into(), theme.value().into()));
+		inputs.push(("max-width".into(), width.to_string()));
+
+		let request = Request {
+			files,
+			entry: entry.map(String::from),
+			format,
+			pages: if split_pages { Pages::Split } else { Pages::Merged },
+			pixel_per_pt: resolution.pixel_per_pt(),
+			inputs,
+			// Edits keep the date of the original render
+			timestamp: created_at(key),
+			utc_offset,
+			preamble: format!(
+				"{}{}",
+				theme.rules(),
+				if math { MATH_PREAMBLE } else { TYPST_PREAMBLE }
+			),
+			// The display math is closed on its own line so that the formula is left untouched
+			code: if math { format!("{code}\n$") } else { code.into() },
+		};
+
+		let mut command = self.workers.acquire().map_err(InteractionError::Worker)?;
+
+		let mut stdin = command.stdin.take().expect("stdin must have been piped");
+		typscord_protocol::write_async(&mut stdin, &request)
 			.await
-			.expect("stdin must be writable");
+			.map_err(InteractionError::Crashed)?;
+		drop(stdin);
 
-		let stdout = command.stdout.take().expect("stdout must have been piped");
-		let mut stdout = BufReader::new(stdout);
+		let mut stdout = command.stdout.take().expect("stdout must have been piped");
 
 		let now = Instant::now();
-		let result =
-			tokio::time::timeout(self.compilation_timeout, buffer::read_usize(&mut stdout)).await;
+		let result = tokio::select! {
+			result = tokio::time::timeout(
+				self.compilation_timeout,
+				typscord_protocol::read_async::<Response>(&mut stdout),
+			) => result,
+			() = cancel.notified() => {
+				if let Err(error) = command.kill().await {
+					error!(?error, "failed to kill cancelled worker process");
+				}
+				return Err(InteractionError::Cancelled);
+			}
+		};
 		let elapsed_ms = now.elapsed().as_millis();
 		info!(millis = elapsed_ms, "compilation timer");
 
-		let http = self.http.interaction(application_id, token);
-		match result {
-			Ok(result) => match Self::critical_section(stdout, &mut command, result).await {
-				Ok((file, embeds)) => {
-					// Should have exited by now
-					drop(command);
-
-					// Replace previously rendered code block with the rendered attachment
-					if !file.is_empty() {
-						http.replace_response_with_attachments(&[Attachment {
-							description: None,
-							file,
-							filename: From::from(if spoiler {
-								"SPOILER_typst.webp"
-							} else {
-								"typst.webp"
-							}),
-							id: 0,
-						}])
-						.await
-						.expect("original response replacement must succeed");
-					}
+		let Ok(result) = result else {
+			// We need to preemptively kill the process or else we'll risk running infinite
+			// loops in the background. Dropping the child kills it as a last resort.
+			if let Err(error) = command.kill().await {
+				error!(?error, "failed to kill lagging worker process");
+			}
+			return Err(InteractionError::Timeout(elapsed_ms));
+		};
 
-					let value = format!("Compiled in **{elapsed_ms}ms**.");
-					http.create_ephemeral_followup_with_embeds(&value, &embeds)
-						.await
-						.expect("ephemeral followup must succeed");
-				}
+		let Output { render, timing, embeds } =
+			match Self::critical_section(stdout, &mut command, result).await {
+				Ok(output) => output,
 				Err(error) => {
-					error!(?error, "worker process crashed");
-
-					// Reap the resources from the child process for proper garbage collection
-					command.kill().await.expect("crashed worker process must be killed");
+					// Only a worker that exits on its own tells why it failed. Otherwise, our own
+					// kill would pass for the `SIGKILL` of the CPU limit.
+					let status = match tokio::time::timeout(EXIT_GRACE_PERIOD, command.wait()).await
+					{
+						Ok(status) => Some(status.map_err(InteractionError::Worker)?),
+						Err(_) => {
+							// Reap the resources from the child process for proper garbage collection
+							if let Err(error) = command.kill().await {
+								error!(?error, "failed to kill crashed worker process");
+							}
+							None
+						}
+					};
+					error!(?status, "worker process failed");
 
-					http.update_response_with_embeds(
-						"The Typst renderer crashed. Please try again with simpler input.",
-						&[],
-					)
-					.await
-					.expect("original followup must succeed");
+					// Allocation failures abort, and the CPU limit sends `SIGXCPU` (then `SIGKILL`)
+					let signal = status.and_then(|status| status.signal());
+					return Err(
+						if matches!(signal, Some(libc::SIGABRT | libc::SIGXCPU | libc::SIGKILL)) {
+							InteractionError::ResourceLimit(error)
+						} else {
+							InteractionError::Crashed(error)
+						},
+					);
 				}
-			},
-			Err(error) => {
-				error!(?error, "timeout when compiling code");
+			};
 
-				// We need to preemptively kill the process or else we'll risk running infinite
-				// loops in the background.
-				command.kill().await.expect("lagging worker process must be killed");
-				drop(command);
+		// Should have exited by now
+		drop(command);
 
-				let value = format!(
-					"Compilation timed out after **{elapsed_ms}ms**. Check your code for infinite loops and expensive operations."
-				);
+		let Render { page_count, scale, files } =
+			render.unwrap_or(Render { page_count: 0, scale: 100, files: Vec::new() });
+		let file_count = files.len();
+		let prefix = if spoiler { "SPOILER_" } else { "" };
+		let extension = format.extension();
 
-				http.update_response_with_embeds(&value, &[])
-					.await
-					.expect("original response edit must succeed");
+		// Failed compilations can be edited, too, since fixing them is the main reason to edit
+		let components = match draft {
+			Some(draft) => {
+				self.drafts.insert(key, draft);
+				vec![edit_button(key)]
 			}
+			None => Vec::new(),
+		};
+
+		// Replace previously rendered code block with the rendered attachments
+		if file_count > 0 {
+			let attachments = files
+				.into_iter()
+				.zip(1..)
+				.map(|(file, page)| Attachment {
+					description: None,
+					file,
+					filename: if file_count == 1 {
+						format!("{prefix}typst.{extension}")
+					} else {
+						format!("{prefix}typst-{page}.{extension}")
+					},
+					id: page,
+				})
+				.collect::<Vec<_>>();
+
+			http.replace_response_with_attachments(reference.as_deref(), &attachments, &components)
+				.await?;
 		}
-	}
 
-	#[instrument(skip_all)]
-	async fn critical_section<Stdout: AsyncBufRead + Unpin>(
-		mut stdout: Stdout,
-		command: &mut Child,
-		warning_count: io::Result<usize>,
-	) -> io::Result<(Vec<u8>, Vec<Embed>)> {
-		let warning_count = warning_count?;
-		info!(warnings = warning_count, "read warning count");
-
-		// Shared string buffer whose capacity can be reused by everyone
-		let mut buffer = String::new();
-
-		let mut warning_embed_fields = Vec::<EmbedField>::new();
-		for _ in 0..warning_count {
-			warning_embed_fields.push(EmbedField {
-				name: buffer::read_line(&mut stdout, &mut buffer).await?,
-				value: buffer::read_line(&mut stdout, &mut buffer).await?,
-				inline: false,
-			});
+		let Timing { compile, export } = timing;
+		let mut value = format!(
+			"Compiled in **{}ms** and exported in **{}ms**.",
+			compile.as_millis(),
+			export.as_millis()
+		);
+		if scale < 100 {
+			value.push_str(&format!(
+				" Downscaled to **{scale}%** of the resolution to fit Discord's upload limit."
+			));
+		}
+		// PDFs hold every page in a single file
+		if split_pages && format != Format::Pdf && page_count > file_count {
+			let dropped = page_count - file_count;
+			value.push_str(&format!(
+				" Only the first {file_count} of {page_count} pages were attached ({dropped} dropped)."
+			));
 		}
 
-		let error_count = buffer::read_usize(&mut stdout).await?;
-		info!(errors = error_count, "reading error count");
+		// Failed compilations of fresh renders have nothing else to replace the status message with
+		if file_count == 0 && !update {
+			let content = with_reference(reference.as_deref(), &value);
+			http.update_response_with_embeds(&content, &embeds, &components).await?;
+			return Ok(());
+		}
 
-		let mut error_embed_fields = Vec::<EmbedField>::new();
-		for _ in 0..error_count {
-			error_embed_fields.push(EmbedField {
-				name: buffer::read_line(&mut stdout, &mut buffer).await?,
-				value: buffer::read_line(&mut stdout, &mut buffer).await?,
-				inline: false,
-			});
+		// The render is already visible, so a failed followup is not worth reporting
+		if let Err(error) = http.create_ephemeral_followup_with_embeds(&value, &embeds).await {
+			error!(?error, "failed to send ephemeral followup");
 		}
 
-		// No need for the shared buffer after this point.
-		drop(buffer);
+		Ok(())
+	}
 
-		let mut file = Vec::new();
-		stdout.read_to_end(&mut file).await?;
+	#[instrument(skip_all)]
+	async fn critical_section(
+		stdout: ChildStdout,
+		command: &mut Child,
+		response: Result<Response, ProtocolError>,
+	) -> Result<Output, ProtocolError> {
+		let Response { warnings, result, timing } = response?;
+		info!(warnings = warnings.len(), ?timing, "read worker response");
 
 		// Should close the pipe after this point
 		drop(stdout);
@@ -532,6 +1183,15 @@ impl InteractionHandler {
 		let status = command.wait().await?;
 		info!(?status, "worker process exited");
 
+		let warning_embed_fields = warnings.into_iter().map(embed_field).collect::<Vec<_>>();
+		let (render, error_embed_fields) = match result {
+			Ok(render) => (Some(render), Vec::new()),
+			Err(errors) => {
+				info!(errors = errors.len(), "compilation failed");
+				(None, errors.into_iter().map(embed_field).collect())
+			}
+		};
+
 		// Send errors/warnings as an ephemeral followup
 		let mut embeds = Vec::<Embed>::with_capacity(2);
 
@@ -571,6 +1231,278 @@ impl InteractionHandler {
 			});
 		}
 
-		Ok((file, embeds))
+		Ok(Output { render, timing, embeds })
+	}
+}
+
+/// Discord's epoch (the first second of 2015) in milliseconds since the Unix epoch.
+const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+/// Snowflakes start with the milliseconds since the [`DISCORD_EPOCH`].
+fn created_at(id: InteractionId) -> Duration {
+	Duration::from_millis((id.get() >> 22) + DISCORD_EPOCH)
+}
+
+/// Discord rejects embeds whose field names, field values, or descriptions exceed these lengths.
+const MAX_FIELD_NAME_LENGTH: usize = 256;
+const MAX_FIELD_VALUE_LENGTH: usize = 1024;
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+
+/// The value lists where the diagnostic occurred (with an excerpt of the code), the first hint,
+/// and the calls that led up to it.
+fn embed_field(Diagnostic { message, hints, location, trace }: Diagnostic) -> EmbedField {
+	let mut value = String::new();
+
+	if let Some(location) = &location {
+		let excerpt = &location.excerpt;
+		value.push_str(&format!("{}\n```\n{excerpt}\n```\n", describe(location)));
+	}
+
+	match hints.first() {
+		Some(hint) => value.push_str(&format!("**Hint:** {hint}")),
+		None => value.push_str("No hints provided."),
+	}
+
+	for Trace { message, location } in trace {
+		value.push_str(&format!("\n- {message}"));
+		if let Some(location) = &location {
+			value.push_str(&format!(" ({})", describe(location)));
+		}
+	}
+
+	EmbedField {
+		name: truncate(message, MAX_FIELD_NAME_LENGTH),
+		value: truncate(value, MAX_FIELD_VALUE_LENGTH),
+		inline: false,
+	}
+}
+
+fn describe(Location { path, line, column, .. }: &Location) -> String {
+	match path {
+		Some(path) => format!("**Line {line}, column {column}** of `{path}`"),
+		None => format!("**Line {line}, column {column}**"),
+	}
+}
+
+/// Cuts the text at a character boundary and appends an ellipsis if it exceeds the limit.
+fn truncate(mut text: String, limit: usize) -> String {
+	if text.chars().count() > limit {
+		let (index, _) = text.char_indices().nth(limit - 1).expect("text exceeds the limit");
+		text.truncate(index);
+		text.push('…');
+	}
+	text
+}
+
+fn ephemeral_message(content: String) -> InteractionResponse {
+	InteractionResponse {
+		kind: InteractionResponseType::ChannelMessageWithSource,
+		data: Some(InteractionResponseData {
+			content: Some(content),
+			flags: Some(MessageFlags::EPHEMERAL),
+			..Default::default()
+		}),
+	}
+}
+
+fn edit_button(key: InteractionId) -> Component {
+	Component::ActionRow(ActionRow {
+		id: None,
+		components: vec![Component::Button(Button {
+			id: None,
+			style: ButtonStyle::Secondary,
+			emoji: Some(EmojiReactionType::Unicode { name: String::from('✏') }),
+			label: Some(String::from("Edit")),
+			url: None,
+			custom_id: Some(format!("edit:{key}")),
+			sku_id: None,
+			disabled: false,
+		})],
+	})
+}
+
+/// Failed renders of a message still link back to it, just like the rendered attachments.
+fn with_reference(reference: Option<&str>, content: &str) -> String {
+	match reference {
+		Some(reference) => format!("{reference}\n{content}"),
+		None => content.into(),
+	}
+}
+
+fn queued_status(position: usize) -> String {
+	format!("Queued (position {position})…")
+}
+
+/// Status updates are merely cosmetic, so failing to send one must not abort the render.
+async fn report_status(http: &HttpInteraction<'_>, content: &str, key: InteractionId) {
+	if let Err(error) = http.update_response_with_components(content, &[cancel_button(key)]).await {
+		error!(?error, "failed to update render status");
+	}
+}
+
+fn cancel_button(key: InteractionId) -> Component {
+	Component::ActionRow(ActionRow {
+		id: None,
+		components: vec![Component::Button(Button {
+			id: None,
+			style: ButtonStyle::Danger,
+			emoji: None,
+			label: Some(String::from("Cancel")),
+			url: None,
+			custom_id: Some(format!("cancel:{key}")),
+			sku_id: None,
+			disabled: false,
+		})],
+	})
+}
+
+/// The modal is prefilled with the draft, which only carries the options of the command unless a
+/// previous render is being edited.
+fn typst_modal(custom_id: String, draft: &Draft) -> InteractionResponse {
+	let Options { format: selected, split_pages, spoiler, math, .. } = draft.options;
+
+	let (title, label, description, placeholder) = if math {
+		(
+			"Render Math",
+			"Formula",
+			"Typst math without the surrounding dollar signs. Long renders are aborted.",
+			"sum_(k=1)^n k = (n(n+1))/2",
+		)
+	} else {
+		(
+			"Render Typst Code",
+			"Typst Code",
+			"Attachments can be referenced by filename. Only approved packages work. Long renders are aborted.",
+			"Hello, Typst!",
+		)
+	};
+
+	InteractionResponse {
+		kind: InteractionResponseType::Modal,
+		data: Some(InteractionResponseData {
+			flags: Some(MessageFlags::IS_COMPONENTS_V2),
+			custom_id: Some(custom_id),
+			title: Some(title.into()),
+			components: Some(vec![
+				Component::Label(Label {
+					id: None,
+					label: label.into(),
+					description: Some(description.into()),
+					component: Box::new(Component::TextInput(TextInput {
+						id: None,
+						custom_id: "code".into(),
+						#[expect(deprecated, reason = "not actually used")]
+						label: None,
+						style: TextInputStyle::Paragraph,
+						max_length: Some(MAX_CODE_LENGTH as u16),
+						placeholder: Some(placeholder.into()),
+						required: Some(true),
+						value: (!draft.code.is_empty()).then(|| draft.code.clone().into()),
+						min_length: None,
+					})),
+				}),
+				Component::Label(Label {
+					id: None,
+					label: "Mark as Spoiler?".into(),
+					description: Some(
+						"Whether to hide the rendered image behind a spoiler.".into(),
+					),
+					component: Box::new(Component::SelectMenu(SelectMenu {
+						id: None,
+						custom_id: "spoiler".into(),
+						kind: SelectMenuType::Text,
+						disabled: false,
+						options: Some(vec![
+							SelectMenuOption {
+								default: !spoiler,
+								description: None,
+								emoji: None,
+								label: "No".into(),
+								value: "no".into(),
+							},
+							SelectMenuOption {
+								default: spoiler,
+								description: None,
+								emoji: None,
+								label: "Yes".into(),
+								value: "yes".into(),
+							},
+						]),
+						placeholder: None,
+						min_values: None,
+						max_values: None,
+						default_values: None,
+						channel_types: None,
+						required: None,
+					})),
+				}),
+				Component::Label(Label {
+					id: None,
+					label: "Multi-Page Documents".into(),
+					description: Some(
+						"Whether to attach each page separately (up to 10 pages).".into(),
+					),
+					component: Box::new(Component::SelectMenu(SelectMenu {
+						id: None,
+						custom_id: "pages".into(),
+						kind: SelectMenuType::Text,
+						disabled: false,
+						options: Some(vec![
+							SelectMenuOption {
+								default: !split_pages,
+								description: None,
+								emoji: None,
+								label: "Merge All Pages".into(),
+								value: "merged".into(),
+							},
+							SelectMenuOption {
+								default: split_pages,
+								description: None,
+								emoji: None,
+								label: "One Attachment per Page".into(),
+								value: "split".into(),
+							},
+						]),
+						placeholder: None,
+						min_values: None,
+						max_values: None,
+						default_values: None,
+						channel_types: None,
+						required: None,
+					})),
+				}),
+				Component::Label(Label {
+					id: None,
+					label: "Output Format".into(),
+					description: Some("The file format of the rendered output.".into()),
+					component: Box::new(Component::SelectMenu(SelectMenu {
+						id: None,
+						custom_id: "format".into(),
+						kind: SelectMenuType::Text,
+						disabled: false,
+						options: Some(
+							Format::ALL
+								.into_iter()
+								.filter(|&format| format::is_available(format))
+								.map(|format| SelectMenuOption {
+									default: format == selected,
+									description: Some(format::description(format).into()),
+									emoji: None,
+									label: format::label(format).into(),
+									value: format.extension().into(),
+								})
+								.collect(),
+						),
+						placeholder: None,
+						min_values: None,
+						max_values: None,
+						default_values: None,
+						channel_types: None,
+						required: None,
+					})),
+				}),
+			]),
+			..Default::default()
+		}),
 	}
 }
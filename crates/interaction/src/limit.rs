@@ -0,0 +1,177 @@
+use core::time::Duration;
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+use twilight_model::id::{Id, marker::UserMarker};
+
+type UserId = Id<UserMarker>;
+
+/// Each user may render a few times in a row, but only once every few seconds in the long run.
+const USER_QUOTA: Quota = Quota { burst: 5, period: Duration::from_secs(6) };
+
+/// Bounds the total rate of renders regardless of who asks for them.
+const GLOBAL_QUOTA: Quota = Quota { burst: 30, period: Duration::from_millis(500) };
+
+/// Buckets that have refilled completely are forgotten once this many users are tracked.
+const MAX_TRACKED_USERS: usize = 4096;
+
+/// Up to `burst` tokens, with one token added every `period`.
+#[derive(Clone, Copy)]
+struct Quota {
+	burst: u32,
+	period: Duration,
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+impl Bucket {
+	fn full(quota: Quota, now: Instant) -> Self {
+		Self { tokens: quota.burst.into(), updated: now }
+	}
+
+	fn refill(&mut self, quota: Quota, now: Instant) {
+		let elapsed = now.saturating_duration_since(self.updated);
+		let tokens = self.tokens + elapsed.as_secs_f64() / quota.period.as_secs_f64();
+		self.tokens = tokens.min(quota.burst.into());
+		self.updated = now;
+	}
+
+	/// How long until the next token is available.
+	fn wait(&self, quota: Quota) -> Duration {
+		if self.tokens >= 1. { Duration::ZERO } else { quota.period.mul_f64(1. - self.tokens) }
+	}
+}
+
+/// Token buckets for every user plus a global one. A render needs a token from both.
+pub struct RateLimiter {
+	global: Mutex<Bucket>,
+	users: Mutex<HashMap<UserId, Bucket>>,
+}
+
+impl RateLimiter {
+	pub fn new() -> Self {
+		let now = Instant::now();
+		Self { global: Mutex::new(Bucket::full(GLOBAL_QUOTA, now)), users: Mutex::default() }
+	}
+
+	/// Takes a token from both buckets, or else returns how long to wait until the next attempt
+	/// can succeed. Nothing is taken unless both buckets have a token.
+	pub fn acquire(&self, user: UserId) -> Result<(), Duration> {
+		self.acquire_at(user, Instant::now())
+	}
+
+	fn acquire_at(&self, user: UserId, now: Instant) -> Result<(), Duration> {
+		let mut users = self.users.lock().expect("rate limiter must not be poisoned");
+		let mut global = self.global.lock().expect("rate limiter must not be poisoned");
+
+		if users.len() >= MAX_TRACKED_USERS {
+			users.retain(|_, bucket| {
+				bucket.refill(USER_QUOTA, now);
+				bucket.tokens < USER_QUOTA.burst.into()
+			});
+		}
+
+		let bucket = users.entry(user).or_insert_with(|| Bucket::full(USER_QUOTA, now));
+		bucket.refill(USER_QUOTA, now);
+		global.refill(GLOBAL_QUOTA, now);
+
+		let wait = bucket.wait(USER_QUOTA).max(global.wait(GLOBAL_QUOTA));
+		if !wait.is_zero() {
+			return Err(wait);
+		}
+
+		bucket.tokens -= 1.;
+		global.tokens -= 1.;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn user(id: u64) -> UserId {
+		Id::new(id)
+	}
+
+	#[test]
+	fn user_burst_and_refill() {
+		let limiter = RateLimiter::new();
+		let start = Instant::now();
+		for _ in 0..USER_QUOTA.burst {
+			limiter.acquire_at(user(1), start).unwrap();
+		}
+
+		let wait = limiter.acquire_at(user(1), start).unwrap_err();
+		assert_eq!(wait, USER_QUOTA.period);
+
+		// Other users have their own bucket
+		limiter.acquire_at(user(2), start).unwrap();
+
+		let later = start + USER_QUOTA.period / 2;
+		let wait = limiter.acquire_at(user(1), later).unwrap_err();
+		assert_eq!(wait, USER_QUOTA.period / 2);
+
+		limiter.acquire_at(user(1), start + USER_QUOTA.period).unwrap();
+		limiter.acquire_at(user(1), start + USER_QUOTA.period).unwrap_err();
+	}
+
+	#[test]
+	fn global_burst_and_refill() {
+		let limiter = RateLimiter::new();
+		let start = Instant::now();
+		for id in 1..=u64::from(GLOBAL_QUOTA.burst) {
+			limiter.acquire_at(user(id), start).unwrap();
+		}
+
+		let wait = limiter.acquire_at(user(1000), start).unwrap_err();
+		assert_eq!(wait, GLOBAL_QUOTA.period);
+		limiter.acquire_at(user(1000), start + GLOBAL_QUOTA.period).unwrap();
+	}
+
+	#[test]
+	fn rejection_takes_nothing() {
+		let limiter = RateLimiter::new();
+		let start = Instant::now();
+		for _ in 0..USER_QUOTA.burst {
+			limiter.acquire_at(user(1), start).unwrap();
+		}
+		for _ in 0..100 {
+			limiter.acquire_at(user(1), start).unwrap_err();
+		}
+
+		// The global bucket still has all the tokens that the rejected attempts did not take
+		let remaining = GLOBAL_QUOTA.burst - USER_QUOTA.burst;
+		for id in 0..u64::from(remaining) {
+			limiter.acquire_at(user(100 + id), start).unwrap();
+		}
+		limiter.acquire_at(user(1000), start).unwrap_err();
+	}
+
+	#[test]
+	fn forgets_full_buckets() {
+		let limiter = RateLimiter::new();
+		let start = Instant::now();
+
+		// Stay within the global quota by spacing out the renders
+		let mut now = start;
+		for id in 1..=MAX_TRACKED_USERS as u64 {
+			limiter.acquire_at(user(id), now).unwrap();
+			now += GLOBAL_QUOTA.period;
+		}
+		assert_eq!(limiter.users.lock().unwrap().len(), MAX_TRACKED_USERS);
+
+		// Only the users whose buckets have yet to refill are kept
+		let newcomer = user(MAX_TRACKED_USERS as u64 + 1);
+		limiter.acquire_at(newcomer, now).unwrap();
+		let users = limiter.users.lock().unwrap();
+		// Those who rendered less than a period ago
+		let recent = (USER_QUOTA.period.as_millis() / GLOBAL_QUOTA.period.as_millis()) as usize - 1;
+		assert_eq!(users.len(), recent + 1);
+		assert!(users.contains_key(&newcomer));
+		assert!(users.contains_key(&user(MAX_TRACKED_USERS as u64)));
+		assert!(!users.contains_key(&user(1)));
+	}
+}
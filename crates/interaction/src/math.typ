@@ -0,0 +1,4 @@
+#set page(width: auto, height: auto, margin: 0pt)
+#set text(size: 18pt)
+#show: box.with(inset: 4pt)
+$
@@ -0,0 +1,60 @@
+/// Extracts the code of every ` ```typ ` or ` ```typst ` block in the message. Messages without
+/// any such block are rendered as a whole.
+pub fn extract_code(content: &str) -> String {
+	let mut code = String::new();
+	let mut rest = content;
+	while let Some(start) = rest.find("```") {
+		let block = &rest[start + 3..];
+		let Some(end) = block.find("```") else {
+			break;
+		};
+		rest = &block[end + 3..];
+
+		// The language tag is everything up to the first line break
+		let Some((tag, body)) = block[..end].split_once('\n') else {
+			continue;
+		};
+
+		if matches!(tag.trim(), "typ" | "typst") {
+			if !code.is_empty() {
+				code.push('\n');
+			}
+			code.push_str(body);
+		}
+	}
+
+	if code.is_empty() { content.into() } else { code }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn extracts_typ_block() {
+		let content = "Look at this:\n```typ\n= Hello\n$x^2$\n```\nNeat, right?";
+		assert_eq!(extract_code(content), "= Hello\n$x^2$\n");
+	}
+
+	#[test]
+	fn extracts_typst_block() {
+		assert_eq!(extract_code("```typst\n#lorem(5)\n```"), "#lorem(5)\n");
+		assert_eq!(extract_code("``` typst \n#lorem(5)\n```"), "#lorem(5)\n");
+	}
+
+	#[test]
+	fn joins_several_blocks() {
+		let content =
+			"```typ\n#let x = 1\n```\nthen\n```rust\nfn main() {}\n```\n```typst\n#x\n```";
+		assert_eq!(extract_code(content), "#let x = 1\n\n#x\n");
+	}
+
+	#[test]
+	fn falls_back_to_whole_message() {
+		for content in
+			["= Hello $x^2$", "```rust\nfn main() {}\n```", "```#inline```", "```typ\nunterminated"]
+		{
+			assert_eq!(extract_code(content), content);
+		}
+	}
+}
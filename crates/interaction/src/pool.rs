@@ -0,0 +1,134 @@
+use core::time::Duration;
+use std::{
+	collections::VecDeque,
+	env, io,
+	path::Path,
+	process::Stdio,
+	sync::{Arc, Mutex, MutexGuard},
+	time::Instant,
+};
+use tokio::process::{Child, Command};
+use tracing::{error, info, warn};
+
+struct Worker {
+	child: Child,
+	spawned_at: Instant,
+}
+
+#[derive(Default)]
+struct Idle {
+	workers: VecDeque<Worker>,
+	/// Replacements that are still being spawned, so that concurrent replenishments do not
+	/// overshoot the size of the pool.
+	spawning: usize,
+}
+
+/// Worker processes are spawned ahead of time so that they have already loaded the fonts and the
+/// standard library by the time a job arrives. Each worker only ever runs a single job, so every
+/// [acquisition](Self::acquire) spawns a replacement in the background.
+pub struct WorkerPool {
+	shared: Arc<Shared>,
+}
+
+struct Shared {
+	exe_path: Box<Path>,
+	size: usize,
+	/// Idle workers older than this are discarded (instead of handed out) upon the next
+	/// acquisition. Nothing happens to them in the meantime.
+	recycle_after: Option<Duration>,
+	/// Address-space limit in MiB passed on to every worker.
+	memory_limit: Option<u64>,
+	idle: Mutex<Idle>,
+}
+
+impl WorkerPool {
+	/// A `size` of zero disables the pool altogether, so workers are spawned on demand.
+	pub fn new(
+		exe_path: Box<Path>,
+		size: usize,
+		recycle_after: Option<Duration>,
+		memory_limit: Option<u64>,
+	) -> io::Result<Self> {
+		let shared = Shared { exe_path, size, recycle_after, memory_limit, idle: Mutex::default() };
+		shared.replenish()?;
+		info!(size, ?recycle_after, "worker pool ready");
+		Ok(Self { shared: Arc::new(shared) })
+	}
+
+	pub fn acquire(&self) -> io::Result<Child> {
+		let worker = {
+			let mut idle = self.shared.lock();
+			loop {
+				let Some(Worker { mut child, spawned_at }) = idle.workers.pop_front() else {
+					break None;
+				};
+
+				// Dropping the child kills the process
+				if self.shared.recycle_after.is_some_and(|age| spawned_at.elapsed() > age) {
+					info!(pid = child.id(), "recycling stale worker");
+					continue;
+				}
+
+				if let Some(status) = child.try_wait()? {
+					warn!(?status, "discarding worker that exited while idle");
+					continue;
+				}
+
+				break Some(child);
+			}
+		};
+
+		// Spawning a process blocks, so the replacements must not hold up the event loop
+		let shared = Arc::clone(&self.shared);
+		tokio::task::spawn_blocking(move || {
+			if let Err(error) = shared.replenish() {
+				error!(?error, "failed to replenish worker pool");
+			}
+		});
+
+		match worker {
+			Some(child) => Ok(child),
+			None => {
+				warn!("no idle workers available, spawning on demand");
+				self.shared.spawn()
+			}
+		}
+	}
+}
+
+impl Shared {
+	fn lock(&self) -> MutexGuard<'_, Idle> {
+		self.idle.lock().expect("worker pool must not be poisoned")
+	}
+
+	/// The lock is only held for the bookkeeping, never while a process is being spawned.
+	fn replenish(&self) -> io::Result<()> {
+		loop {
+			{
+				let mut idle = self.lock();
+				if idle.workers.len() + idle.spawning >= self.size {
+					return Ok(());
+				}
+				idle.spawning += 1;
+			}
+
+			let child = self.spawn();
+			let mut idle = self.lock();
+			idle.spawning -= 1;
+			idle.workers.push_back(Worker { child: child?, spawned_at: Instant::now() });
+		}
+	}
+
+	fn spawn(&self) -> io::Result<Child> {
+		// The worker must not see secrets such as the bot token, so only its own configuration
+		// (and the log filter) is passed through.
+		let mut command = Command::new(self.exe_path.as_os_str());
+		command.arg("worker").env_clear().envs(env::vars_os().filter(|(key, _)| {
+			key.to_str().is_some_and(|key| key.starts_with("TYPSCORD_") || key == "RUST_LOG")
+		}));
+		if let Some(limit) = self.memory_limit {
+			command.env("TYPSCORD_WORKER_MEMORY_LIMIT", limit.to_string());
+		}
+		command.stdin(Stdio::piped()).stdout(Stdio::piped()).kill_on_drop(true).spawn()
+	}
+}
@@ -10,5 +10,11 @@
 		}
 	}
 }
-#show: adaptive-width.with(max-width: 460pt)
-#show: box.with(inset: 10pt)
+#let max-width = int(sys.inputs.at("max-width", default: "460")) * 1pt
+// Page breaks are not allowed inside the box, so split pages keep a fixed width instead
+#show: body => if sys.inputs.at("split-pages", default: "false") == "true" {
+	set page(width: max-width, height: auto, margin: 10pt)
+	body
+} else {
+	adaptive-width(max-width: max-width, box(inset: 10pt, body))
+}
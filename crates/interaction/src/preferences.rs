@@ -0,0 +1,81 @@
+use crate::theme::Theme;
+
+/// Offsets beyond these many hours do not exist anywhere.
+const MAX_UTC_OFFSET_HOURS: i32 = 14;
+
+/// Remembered across the renders of a user.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Preferences {
+	pub theme: Theme,
+	/// Default offset of `datetime.today()` from UTC in seconds.
+	pub utc_offset: i32,
+}
+
+/// Parses offsets such as `UTC`, `+8`, `-03:30`, `+0545`, or `UTC+10` into seconds.
+pub fn parse_utc_offset(value: &str) -> Option<i32> {
+	let value = value.trim().to_ascii_uppercase();
+	let value = value.strip_prefix("UTC").or_else(|| value.strip_prefix("GMT")).unwrap_or(&value);
+	if value.is_empty() || value == "Z" {
+		return Some(0);
+	}
+
+	let (sign, offset) = match value.split_at_checked(1)? {
+		("+", offset) => (1, offset),
+		("-", offset) => (-1, offset),
+		_ => return None,
+	};
+	// Anything else (e.g., `+123`) is ambiguous, and long digit runs would overflow
+	let (hours, minutes) = match (offset.split_once(':'), offset.len()) {
+		(Some((hours, minutes)), _) if (1..=2).contains(&hours.len()) && minutes.len() == 2 => {
+			(hours, minutes)
+		}
+		(None, 1..=2) => (offset, "0"),
+		(None, 4) => offset.split_at_checked(2)?,
+		_ => return None,
+	};
+
+	// Signs are only allowed at the front
+	if !hours.chars().chain(minutes.chars()).all(|c| c.is_ascii_digit()) {
+		return None;
+	}
+	let hours = hours.parse::<i32>().ok()?;
+	let minutes = minutes.parse::<i32>().ok()?;
+	if minutes >= 60 || hours * 60 + minutes > MAX_UTC_OFFSET_HOURS * 60 {
+		return None;
+	}
+
+	Some(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_valid_offsets() {
+		assert_eq!(parse_utc_offset("UTC"), Some(0));
+		assert_eq!(parse_utc_offset(" z "), Some(0));
+		assert_eq!(parse_utc_offset("+8"), Some(8 * 3600));
+		assert_eq!(parse_utc_offset("utc+10"), Some(10 * 3600));
+		assert_eq!(parse_utc_offset("GMT-03:30"), Some(-(3 * 3600 + 30 * 60)));
+		assert_eq!(parse_utc_offset("+5:30"), Some(5 * 3600 + 30 * 60));
+		assert_eq!(parse_utc_offset("+0545"), Some(5 * 3600 + 45 * 60));
+		assert_eq!(parse_utc_offset("+14:00"), Some(14 * 3600));
+	}
+
+	#[test]
+	fn rejects_ambiguous_offsets() {
+		for value in
+			["+123", "+12345", "+1:3", "+:30", "+12:", "8", "++8", "+-8", "+8:-3", "+0:60", "+1é1"]
+		{
+			assert_eq!(parse_utc_offset(value), None, "{value}");
+		}
+	}
+
+	#[test]
+	fn rejects_overflowing_offsets() {
+		for value in ["+14:01", "-15", "+99999999900", "+7158278900", "+4294967296:00", "+99:99"] {
+			assert_eq!(parse_utc_offset(value), None, "{value}");
+		}
+	}
+}
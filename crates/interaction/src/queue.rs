@@ -0,0 +1,153 @@
+use std::{
+	collections::VecDeque,
+	sync::{
+		Arc, Mutex,
+		atomic::{AtomicU64, Ordering},
+	},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, watch};
+
+/// Renders beyond the concurrency limit wait here in FIFO order. Only the job at the front of the
+/// queue waits for a permit, so later jobs cannot overtake it.
+pub struct Queue {
+	permits: Arc<Semaphore>,
+	capacity: usize,
+	waiting: Mutex<VecDeque<u64>>,
+	next_ticket: AtomicU64,
+	/// Notified whenever a job leaves the queue.
+	changed: watch::Sender<()>,
+}
+
+/// A place in the [`Queue`]. Dropping the ticket leaves the queue.
+pub struct Ticket {
+	queue: Arc<Queue>,
+	id: u64,
+	changed: watch::Receiver<()>,
+}
+
+impl Queue {
+	pub fn new(max_concurrent: usize, capacity: usize) -> Self {
+		// Otherwise, every job would wait until it expires
+		assert!(max_concurrent > 0, "at least one render must be able to run");
+		Self {
+			permits: Arc::new(Semaphore::new(max_concurrent)),
+			capacity,
+			waiting: Mutex::default(),
+			next_ticket: AtomicU64::new(0),
+			changed: watch::Sender::new(()),
+		}
+	}
+
+	/// Returns [`None`] if the queue is full.
+	pub fn enqueue(self: &Arc<Self>) -> Option<Ticket> {
+		let mut waiting = self.waiting.lock().expect("queue must not be poisoned");
+		if waiting.len() >= self.capacity {
+			return None;
+		}
+
+		let id = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+		waiting.push_back(id);
+		Some(Ticket { queue: Arc::clone(self), id, changed: self.changed.subscribe() })
+	}
+}
+
+impl Ticket {
+	/// One-based position in the queue, or [`None`] if the job can start right away.
+	pub fn position(&self) -> Option<usize> {
+		let ahead = self.ahead();
+		(ahead > 0 || self.queue.permits.available_permits() == 0).then_some(ahead + 1)
+	}
+
+	pub fn is_next(&self) -> bool {
+		self.ahead() == 0
+	}
+
+	/// Resolves once any job has left the queue since the last call.
+	pub async fn advanced(&mut self) {
+		// The ticket keeps the queue (and thus the sender) alive
+		self.changed.changed().await.expect("queue must outlive its tickets");
+	}
+
+	/// Waits for a free slot, which must only be done at the front of the queue. The job leaves
+	/// the queue as soon as it has a slot.
+	pub async fn admit(self) -> OwnedSemaphorePermit {
+		Arc::clone(&self.queue.permits).acquire_owned().await.expect("permits must never be closed")
+	}
+
+	fn ahead(&self) -> usize {
+		let waiting = self.queue.waiting.lock().expect("queue must not be poisoned");
+		waiting.iter().position(|&id| id == self.id).expect("ticket must still be queued")
+	}
+}
+
+impl Drop for Ticket {
+	fn drop(&mut self) {
+		let mut waiting = self.queue.waiting.lock().expect("queue must not be poisoned");
+		if let Some(index) = waiting.iter().position(|&id| id == self.id) {
+			waiting.remove(index);
+		}
+		drop(waiting);
+		self.queue.changed.send_modify(|()| {});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::time::Duration;
+	use tokio::time::timeout;
+
+	#[tokio::test]
+	async fn positions_follow_fifo_order() {
+		let queue = Arc::new(Queue::new(1, 3));
+		let first = queue.enqueue().unwrap();
+		let mut second = queue.enqueue().unwrap();
+		let third = queue.enqueue().unwrap();
+		assert!(queue.enqueue().is_none(), "queue must be bounded");
+
+		// The first job can start right away
+		assert_eq!(first.position(), None);
+		assert!(first.is_next());
+		assert_eq!(second.position(), Some(2));
+		assert_eq!(third.position(), Some(3));
+		assert!(!second.is_next());
+
+		let permit = first.admit().await;
+		second.advanced().await;
+		assert_eq!(second.position(), Some(1));
+		assert_eq!(third.position(), Some(2));
+
+		// Only the job at the front of the queue gets the next free slot
+		assert!(second.is_next());
+		let admit = second.admit();
+		tokio::pin!(admit);
+		assert!(timeout(Duration::from_millis(10), &mut admit).await.is_err());
+		drop(permit);
+		let _permit = admit.await;
+		assert_eq!(third.position(), Some(1));
+
+		// Leaving the queue makes room for another job
+		assert!(queue.enqueue().is_some());
+	}
+
+	#[tokio::test]
+	async fn dropped_tickets_leave_the_queue() {
+		let queue = Arc::new(Queue::new(1, 4));
+		let _permit = queue.enqueue().unwrap().admit().await;
+		let first = queue.enqueue().unwrap();
+		let second = queue.enqueue().unwrap();
+		let mut third = queue.enqueue().unwrap();
+		assert_eq!(third.position(), Some(3));
+
+		// Cancelled jobs wake up everyone behind them
+		drop(second);
+		timeout(Duration::from_secs(1), third.advanced()).await.unwrap();
+		assert_eq!(first.position(), Some(1));
+		assert_eq!(third.position(), Some(2));
+
+		drop(first);
+		timeout(Duration::from_secs(1), third.advanced()).await.unwrap();
+		assert_eq!(third.position(), Some(1));
+		assert!(third.is_next());
+	}
+}
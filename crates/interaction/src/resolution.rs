@@ -0,0 +1,28 @@
+/// Presets for the resolution of raster formats. Larger resolutions are still downscaled if the
+/// output exceeds Discord's upload limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Resolution {
+	Small,
+	#[default]
+	Normal,
+	Large,
+}
+
+impl Resolution {
+	pub fn from_value(value: &str) -> Option<Self> {
+		Some(match value {
+			"small" => Self::Small,
+			"normal" => Self::Normal,
+			"large" => Self::Large,
+			_ => return None,
+		})
+	}
+
+	pub const fn pixel_per_pt(self) -> f32 {
+		match self {
+			Self::Small => 2.,
+			Self::Normal => 4.,
+			Self::Large => 6.,
+		}
+	}
+}
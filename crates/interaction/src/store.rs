@@ -0,0 +1,74 @@
+use std::{collections::VecDeque, sync::Mutex};
+use twilight_model::id::{Id, marker::InteractionMarker};
+
+pub type InteractionId = Id<InteractionMarker>;
+
+/// State that cannot be round-tripped through Discord (e.g., in a custom ID) is kept here, keyed
+/// by the interaction (or user) that it belongs to. The least recently used entries are evicted
+/// first once the store is full.
+pub struct Store<T, K = InteractionId> {
+	capacity: usize,
+	entries: Mutex<VecDeque<(K, T)>>,
+}
+
+impl<T, K: PartialEq> Store<T, K> {
+	pub const fn new(capacity: usize) -> Self {
+		Self { capacity, entries: Mutex::new(VecDeque::new()) }
+	}
+
+	/// Replaces any existing entry for the same key.
+	pub fn insert(&self, id: K, value: T) {
+		let mut entries = self.entries.lock().expect("store must not be poisoned");
+		if let Some(index) = entries.iter().position(|(key, _)| *key == id) {
+			entries.remove(index);
+		} else if entries.len() >= self.capacity {
+			entries.pop_front();
+		}
+		entries.push_back((id, value));
+	}
+
+	pub fn take(&self, id: K) -> Option<T> {
+		let mut entries = self.entries.lock().expect("store must not be poisoned");
+		let index = entries.iter().position(|(key, _)| *key == id)?;
+		entries.remove(index).map(|(_, value)| value)
+	}
+
+	/// Reading an entry also keeps it from being evicted soon.
+	pub fn get(&self, id: K) -> Option<T>
+	where
+		T: Clone,
+	{
+		let mut entries = self.entries.lock().expect("store must not be poisoned");
+		let index = entries.iter().position(|(key, _)| *key == id)?;
+		let entry = entries.remove(index)?;
+		let value = entry.1.clone();
+		entries.push_back(entry);
+		Some(value)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn evicts_least_recently_used() {
+		let store = Store::<&str, u8>::new(2);
+		store.insert(1, "one");
+		store.insert(2, "two");
+
+		// Reading the oldest entry makes the other one the least recently used
+		assert_eq!(store.get(1), Some("one"));
+		store.insert(3, "three");
+		assert_eq!(store.get(2), None);
+		assert_eq!(store.get(1), Some("one"));
+
+		// Updating an entry refreshes it, too
+		store.insert(3, "drei");
+		store.insert(4, "four");
+		assert_eq!(store.get(1), None);
+		assert_eq!(store.get(3), Some("drei"));
+		assert_eq!(store.take(4), Some("four"));
+		assert_eq!(store.get(4), None);
+	}
+}
@@ -0,0 +1,39 @@
+/// Colors of the rendered page.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Theme {
+	/// Black text on a white page.
+	#[default]
+	Light,
+	/// Matches the background and text colors of Discord's dark theme.
+	Dark,
+	/// Gray text without a page fill, which is legible in either theme.
+	Transparent,
+}
+
+impl Theme {
+	pub fn from_value(value: &str) -> Option<Self> {
+		Some(match value {
+			"light" => Self::Light,
+			"dark" => Self::Dark,
+			"transparent" => Self::Transparent,
+			_ => return None,
+		})
+	}
+
+	pub const fn value(self) -> &'static str {
+		match self {
+			Self::Light => "light",
+			Self::Dark => "dark",
+			Self::Transparent => "transparent",
+		}
+	}
+
+	/// Set rules that are prepended to the preamble.
+	pub const fn rules(self) -> &'static str {
+		match self {
+			Self::Light => "",
+			Self::Dark => "#set page(fill: rgb(\"#313338\"))\n#set text(fill: rgb(\"#dbdee1\"))\n",
+			Self::Transparent => "#set page(fill: none)\n#set text(fill: rgb(\"#80848e\"))\n",
+		}
+	}
+}
@@ -0,0 +1,141 @@
+use crate::error::InteractionError;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::error;
+use twilight_model::{
+	application::interaction::{
+		InteractionDataResolved,
+		application_command::{CommandDataOption, CommandOptionValue},
+	},
+	channel::Attachment,
+};
+use typscord_http::{DownloadError, Http};
+use typscord_protocol::File;
+
+/// Attachments larger than this are rejected before they are even downloaded.
+pub const MAX_UPLOAD_SIZE: u64 = 4 * 1024 * 1024;
+
+/// The combined size of all attachments in a single render.
+pub const MAX_TOTAL_UPLOAD_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Edited renders may still wait this long in the queue before their attachments are downloaded.
+const EXPIRY_MARGIN: u64 = 15 * 60;
+
+/// Drafts only remember where the attachments were, so edits cannot outlive them.
+pub const EXPIRED_UPLOADS: &str = "The attached files of this render have expired. Please run the command again with the files attached.";
+
+/// An attachment that has been validated but not yet downloaded.
+#[derive(Clone, Debug)]
+pub struct Upload {
+	pub filename: Box<str>,
+	pub url: Box<str>,
+	/// Seconds since the Unix epoch at which the signed CDN URL stops working.
+	pub expires_at: Option<u64>,
+}
+
+impl Upload {
+	/// Whether the URL expires before a render that starts now could download it.
+	pub fn is_expired(&self) -> bool {
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs());
+		self.expires_at.is_some_and(|expires_at| expires_at <= now + EXPIRY_MARGIN)
+	}
+}
+
+/// Discord signs attachment URLs with their expiry in the `ex` parameter (in hexadecimal).
+fn expiry(url: &str) -> Option<u64> {
+	let (_, query) = url.split_once('?')?;
+	let ex = query.split('&').find_map(|param| param.strip_prefix("ex="))?;
+	u64::from_str_radix(ex, 16).ok()
+}
+
+/// Validates the attachment options of a command against the size limits. The error is a
+/// user-facing explanation of which limit was exceeded.
+pub fn collect(
+	options: Vec<CommandDataOption>,
+	resolved: Option<InteractionDataResolved>,
+) -> Result<Box<[Upload]>, String> {
+	let mut attachments = resolved.map(|resolved| resolved.attachments).unwrap_or_default();
+	let mut uploads = Vec::<Upload>::new();
+	let mut total_size = 0;
+
+	for CommandDataOption { value, .. } in options {
+		let CommandOptionValue::Attachment(id) = value else {
+			continue;
+		};
+
+		let Some(Attachment { filename, url, size, .. }) = attachments.remove(&id) else {
+			error!(?id, "unresolved attachment");
+			return Err("Discord did not provide the attached file. Please try again.".into());
+		};
+
+		if size > MAX_UPLOAD_SIZE {
+			return Err(upload_size_exceeded(&filename));
+		}
+
+		if uploads.iter().any(|upload| *upload.filename == filename) {
+			return Err(format!("`{filename}` has been attached more than once."));
+		}
+
+		total_size += size;
+		let expires_at = expiry(&url);
+		uploads.push(Upload { filename: filename.into(), url: url.into(), expires_at });
+	}
+
+	if total_size > MAX_TOTAL_UPLOAD_SIZE {
+		return Err(total_size_exceeded());
+	}
+
+	Ok(uploads.into())
+}
+
+/// Downloads the attachments while enforcing the same limits on the bytes that actually arrive,
+/// since the sizes in the attachment metadata are only what Discord claims.
+pub async fn download(http: &Http, uploads: Box<[Upload]>) -> Result<Vec<File>, InteractionError> {
+	let mut files = Vec::with_capacity(uploads.len());
+	let mut remaining = MAX_TOTAL_UPLOAD_SIZE;
+	for Upload { filename, url, .. } in uploads {
+		let limit = MAX_UPLOAD_SIZE.min(remaining);
+		let bytes = match http.download_attachment(&url, limit as usize).await {
+			Ok(bytes) => bytes,
+			Err(DownloadError::TooLarge(_)) if limit < MAX_UPLOAD_SIZE => {
+				return Err(total_size_exceeded().into());
+			}
+			Err(DownloadError::TooLarge(_)) => return Err(upload_size_exceeded(&filename).into()),
+			Err(error) => return Err(InteractionError::Download(filename, error)),
+		};
+		remaining -= bytes.len() as u64;
+		files.push(File { name: filename.into(), bytes });
+	}
+	Ok(files)
+}
+
+fn upload_size_exceeded(filename: &str) -> String {
+	format!("`{filename}` exceeds the {} MiB limit per attachment.", MAX_UPLOAD_SIZE >> 20)
+}
+
+fn total_size_exceeded() -> String {
+	format!("The attachments exceed the {} MiB limit in total.", MAX_TOTAL_UPLOAD_SIZE >> 20)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_expiry() {
+		let url =
+			"https://cdn.discordapp.com/attachments/1/2/a.png?ex=68f3a1b2&is=68f25032&hm=ab12&";
+		assert_eq!(expiry(url), Some(0x68f3a1b2));
+		assert_eq!(expiry("https://cdn.discordapp.com/attachments/1/2/a.png"), None);
+		assert_eq!(expiry("https://cdn.discordapp.com/attachments/1/2/a.png?ex=zz"), None);
+	}
+
+	#[test]
+	fn expires_with_margin() {
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+		let upload = |expires_at| Upload { filename: "a.png".into(), url: "".into(), expires_at };
+		assert!(!upload(None).is_expired());
+		assert!(!upload(Some(now + 2 * EXPIRY_MARGIN)).is_expired());
+		assert!(upload(Some(now + EXPIRY_MARGIN / 2)).is_expired());
+		assert!(upload(Some(now - 1)).is_expired());
+	}
+}
@@ -0,0 +1,348 @@
+//! Messages exchanged between the server and its worker processes over the standard streams.
+//!
+//! Each message is a single frame: a header with the [protocol version](VERSION) and the payload
+//! length (both little-endian `u32`s), followed by the [`bincode`]-encoded payload.
+
+use bincode::{
+	Decode, Encode,
+	config::{Configuration, standard},
+	error::{DecodeError, EncodeError},
+};
+use core::{fmt, time::Duration};
+use std::io::{self, Read, Write};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+
+/// Bumped whenever the layout of a message changes.
+pub const VERSION: u32 = 4;
+
+/// Frames larger than this are rejected before their payload is allocated. This comfortably fits
+/// ten maximum-size attachments.
+pub const MAX_FRAME_SIZE: usize = 128 * 1024 * 1024;
+
+const HEADER_SIZE: usize = 8;
+const CONFIG: Configuration = standard();
+
+#[derive(Debug)]
+pub enum ProtocolError {
+	Io(io::Error),
+	/// The peer speaks a different version of the protocol.
+	Version(u32),
+	/// The payload length exceeds [`MAX_FRAME_SIZE`].
+	TooLarge(usize),
+	Encode(EncodeError),
+	Decode(DecodeError),
+	/// The payload has leftover bytes after the message.
+	TrailingBytes(usize),
+}
+
+impl fmt::Display for ProtocolError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(error) => write!(f, "worker stream failed: {error}"),
+			Self::Version(version) => {
+				write!(f, "expected protocol version {VERSION}, but got {version}")
+			}
+			Self::TooLarge(size) => write!(f, "frame of {size} bytes exceeds the limit"),
+			Self::Encode(error) => write!(f, "malformed message: {error}"),
+			Self::Decode(error) => write!(f, "malformed frame: {error}"),
+			Self::TrailingBytes(count) => write!(f, "frame has {count} trailing bytes"),
+		}
+	}
+}
+
+impl core::error::Error for ProtocolError {
+	fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+		match self {
+			Self::Io(error) => Some(error),
+			Self::Encode(error) => Some(error),
+			Self::Decode(error) => Some(error),
+			Self::Version(_) | Self::TooLarge(_) | Self::TrailingBytes(_) => None,
+		}
+	}
+}
+
+impl From<io::Error> for ProtocolError {
+	fn from(error: io::Error) -> Self {
+		Self::Io(error)
+	}
+}
+
+/// The file format of the rendered output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub enum Format {
+	#[default]
+	WebP,
+	Png,
+	Svg,
+	/// Only available when the server enables the `pdf` feature.
+	Pdf,
+}
+
+impl Format {
+	pub const ALL: [Self; 4] = [Self::WebP, Self::Png, Self::Svg, Self::Pdf];
+
+	/// Raster formats can be downscaled to reduce the file size.
+	pub const fn is_raster(self) -> bool {
+		matches!(self, Self::WebP | Self::Png)
+	}
+
+	pub const fn extension(self) -> &'static str {
+		match self {
+			Self::WebP => "webp",
+			Self::Png => "png",
+			Self::Svg => "svg",
+			Self::Pdf => "pdf",
+		}
+	}
+
+	pub fn from_extension(extension: &str) -> Option<Self> {
+		Self::ALL.into_iter().find(|format| format.extension() == extension)
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub enum Pages {
+	/// Stitches all pages into a single file.
+	#[default]
+	Merged,
+	/// Attaches each page as its own file.
+	Split,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct File {
+	pub name: String,
+	pub bytes: Vec<u8>,
+}
+
+/// Sent by the server to the worker.
+#[derive(Debug, Encode, Decode)]
+pub struct Request {
+	/// Attachments and `.zip` archives that make up the project.
+	pub files: Vec<File>,
+	/// The project file to compile instead of the code.
+	pub entry: Option<String>,
+	pub format: Format,
+	pub pages: Pages,
+	/// Resolution of raster formats before any downscaling.
+	pub pixel_per_pt: f32,
+	/// Exposed to the document as `sys.inputs`.
+	pub inputs: Vec<(String, String)>,
+	/// Time since the Unix epoch that `datetime.today()` is pinned to.
+	pub timestamp: Duration,
+	/// Default offset of `datetime.today()` from UTC in seconds.
+	pub utc_offset: i32,
+	/// Prepended to the entry point. Diagnostics are reported relative to the end of it.
+	pub preamble: String,
+	/// Empty when rendering the entry point of a project.
+	pub code: String,
+}
+
+/// Lines and columns are one-based and match what the user typed.
+#[derive(Debug, Encode, Decode)]
+pub struct Location {
+	/// [`None`] for the user's code (or the entry point of a project).
+	pub path: Option<String>,
+	pub line: usize,
+	pub column: usize,
+	/// The offending line followed by a line of carets underneath the span.
+	pub excerpt: String,
+}
+
+/// A function call, show rule, or import that the diagnostic was raised through.
+#[derive(Debug, Encode, Decode)]
+pub struct Trace {
+	pub message: String,
+	pub location: Option<Location>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Diagnostic {
+	pub message: String,
+	pub hints: Vec<String>,
+	pub location: Option<Location>,
+	pub trace: Vec<Trace>,
+}
+
+#[derive(Debug, Encode, Decode)]
+pub struct Render {
+	/// Total number of pages in the document, including those that were not rendered.
+	pub page_count: usize,
+	/// Percentage of the requested resolution after downscaling to fit the upload limit.
+	pub scale: usize,
+	/// One file per page when the pages are split. Otherwise, just one file.
+	pub files: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Default, Encode, Decode)]
+pub struct Timing {
+	pub compile: Duration,
+	/// Includes every re-export while downscaling.
+	pub export: Duration,
+}
+
+/// Sent by the worker to the server once the job is done.
+#[derive(Debug, Encode, Decode)]
+pub struct Response {
+	pub warnings: Vec<Diagnostic>,
+	pub result: Result<Render, Vec<Diagnostic>>,
+	pub timing: Timing,
+}
+
+/// Encodes the message as a complete frame.
+pub fn encode<T: Encode>(message: &T) -> Result<Vec<u8>, ProtocolError> {
+	let mut frame = vec![0; HEADER_SIZE];
+	bincode::encode_into_std_write(message, &mut frame, CONFIG).map_err(ProtocolError::Encode)?;
+
+	let size = frame.len() - HEADER_SIZE;
+	if size > MAX_FRAME_SIZE {
+		return Err(ProtocolError::TooLarge(size));
+	}
+
+	let (version, length) = frame[..HEADER_SIZE].split_at_mut(4);
+	version.copy_from_slice(&VERSION.to_le_bytes());
+	length.copy_from_slice(&(size as u32).to_le_bytes());
+	Ok(frame)
+}
+
+/// Validates the header and returns the size of the payload that follows it.
+fn decode_header(header: [u8; HEADER_SIZE]) -> Result<usize, ProtocolError> {
+	let (version, length) = header.split_at(4);
+	let version = u32::from_le_bytes(version.try_into().expect("version must be four bytes"));
+	if version != VERSION {
+		return Err(ProtocolError::Version(version));
+	}
+
+	let size = u32::from_le_bytes(length.try_into().expect("length must be four bytes")) as usize;
+	if size > MAX_FRAME_SIZE {
+		return Err(ProtocolError::TooLarge(size));
+	}
+
+	Ok(size)
+}
+
+fn decode_payload<T: Decode<()>>(payload: &[u8]) -> Result<T, ProtocolError> {
+	let (message, read) =
+		bincode::decode_from_slice(payload, CONFIG).map_err(ProtocolError::Decode)?;
+	match payload.len() - read {
+		0 => Ok(message),
+		count => Err(ProtocolError::TrailingBytes(count)),
+	}
+}
+
+pub fn write<T: Encode>(writer: &mut impl Write, message: &T) -> Result<(), ProtocolError> {
+	writer.write_all(&encode(message)?)?;
+	writer.flush()?;
+	Ok(())
+}
+
+pub fn read<T: Decode<()>>(reader: &mut impl Read) -> Result<T, ProtocolError> {
+	let mut header = [0; HEADER_SIZE];
+	reader.read_exact(&mut header)?;
+
+	let mut payload = vec![0; decode_header(header)?];
+	reader.read_exact(&mut payload)?;
+	decode_payload(&payload)
+}
+
+pub async fn write_async<T: Encode>(
+	writer: &mut (impl AsyncWrite + Unpin),
+	message: &T,
+) -> Result<(), ProtocolError> {
+	writer.write_all(&encode(message)?).await?;
+	writer.flush().await?;
+	Ok(())
+}
+
+pub async fn read_async<T: Decode<()>>(
+	reader: &mut (impl AsyncRead + Unpin),
+) -> Result<T, ProtocolError> {
+	let mut header = [0; HEADER_SIZE];
+	reader.read_exact(&mut header).await?;
+
+	let mut payload = vec![0; decode_header(header)?];
+	reader.read_exact(&mut payload).await?;
+	decode_payload(&payload)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn request() -> Request {
+		Request {
+			files: vec![File { name: "data.csv".into(), bytes: vec![1, 2, 3] }],
+			entry: None,
+			format: Format::Png,
+			pages: Pages::Split,
+			pixel_per_pt: 2.,
+			inputs: vec![("theme".into(), "dark".into())],
+			timestamp: Duration::from_secs(1_700_000_000),
+			utc_offset: -3600,
+			preamble: "#set page(width: auto)\n".into(),
+			code: "Hello, world!".into(),
+		}
+	}
+
+	#[test]
+	fn round_trip() {
+		let mut frame = Vec::new();
+		write(&mut frame, &request()).unwrap();
+
+		let Request { files, entry, format, pages, inputs, timestamp, utc_offset, code, .. } =
+			read(&mut frame.as_slice()).unwrap();
+		assert_eq!(files.len(), 1);
+		assert_eq!(files[0].bytes, [1, 2, 3]);
+		assert_eq!(entry, None);
+		assert_eq!(format, Format::Png);
+		assert_eq!(pages, Pages::Split);
+		assert_eq!(inputs, [("theme".into(), "dark".into())]);
+		assert_eq!(timestamp, Duration::from_secs(1_700_000_000));
+		assert_eq!(utc_offset, -3600);
+		assert_eq!(code, "Hello, world!");
+	}
+
+	#[test]
+	fn version_mismatch() {
+		let mut frame = encode(&request()).unwrap();
+		frame[..4].copy_from_slice(&(VERSION + 1).to_le_bytes());
+
+		let error = read::<Request>(&mut frame.as_slice()).unwrap_err();
+		assert!(matches!(error, ProtocolError::Version(version) if version == VERSION + 1));
+	}
+
+	#[test]
+	fn frame_too_large() {
+		let mut frame = VERSION.to_le_bytes().to_vec();
+		frame.extend_from_slice(&(MAX_FRAME_SIZE as u32 + 1).to_le_bytes());
+
+		// Rejected from the header alone without waiting for the payload
+		let error = read::<Request>(&mut frame.as_slice()).unwrap_err();
+		assert!(matches!(error, ProtocolError::TooLarge(size) if size == MAX_FRAME_SIZE + 1));
+	}
+
+	#[test]
+	fn truncated_frame() {
+		let frame = encode(&request()).unwrap();
+
+		let error = read::<Request>(&mut &frame[..frame.len() - 1]).unwrap_err();
+		assert!(
+			matches!(error, ProtocolError::Io(ref error) if error.kind() == io::ErrorKind::UnexpectedEof)
+		);
+
+		let error = read::<Request>(&mut &frame[..HEADER_SIZE - 1]).unwrap_err();
+		assert!(
+			matches!(error, ProtocolError::Io(ref error) if error.kind() == io::ErrorKind::UnexpectedEof)
+		);
+	}
+
+	#[test]
+	fn trailing_bytes() {
+		let mut frame = encode(&Pages::Merged).unwrap();
+		frame[4..HEADER_SIZE].copy_from_slice(&2u32.to_le_bytes());
+		frame.push(0);
+
+		let error = read::<Pages>(&mut frame.as_slice()).unwrap_err();
+		assert!(matches!(error, ProtocolError::TrailingBytes(1)));
+	}
+}
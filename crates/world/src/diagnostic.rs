@@ -0,0 +1,94 @@
+use crate::World;
+use typscord_protocol::Location;
+use typst::{World as _, WorldExt as _, syntax::Span};
+
+/// Excerpts are cropped to this many characters around the start of the span.
+const MAX_EXCERPT_WIDTH: usize = 80;
+
+impl World {
+	/// Lines in the entry point are counted from the end of the preamble, so spans inside the
+	/// preamble itself (and detached spans) resolve to [`None`].
+	pub fn locate(&self, span: Span) -> Option<Location> {
+		let id = span.id()?;
+		let source = self.source(id).ok()?;
+		let range = self.range(span)?;
+
+		let lines = source.lines();
+		let (line, column) = lines.byte_to_line_column(range.start)?;
+		let line_range = lines.line_to_range(line)?;
+		let text = source.text()[line_range.clone()].trim_end_matches(['\r', '\n']);
+
+		// Multi-line spans are only underlined until the end of the first line
+		let end = range.end.clamp(range.start, line_range.start + text.len());
+		let width = source.text()[range.start..end].chars().count().max(1);
+
+		// Keep some context before the span while cropping overly long lines
+		let skip = column.saturating_sub(MAX_EXCERPT_WIDTH / 4);
+		let cropped = text.chars().skip(skip).take(MAX_EXCERPT_WIDTH).collect::<String>();
+		let padding = cropped
+			.chars()
+			.take(column - skip)
+			.map(|c| if c == '\t' { '\t' } else { ' ' })
+			.collect::<String>();
+		let carets = "^".repeat(width.min(MAX_EXCERPT_WIDTH - (column - skip)).max(1));
+		let excerpt = format!("{cropped}\n{padding}{carets}");
+
+		let (path, line) = if id == self.main {
+			(None, line.checked_sub(self.preamble_lines)?)
+		} else {
+			let path = id.vpath().as_rooted_path().display();
+			let path = match id.package() {
+				Some(spec) => format!("{spec}{path}"),
+				None => path.to_string(),
+			};
+			(Some(path), line)
+		};
+
+		Some(Location { path, line: line + 1, column: column + 1, excerpt })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Project;
+	use typst::{diag::SourceResult, layout::PagedDocument};
+
+	const PREAMBLE: &str = "#set page(width: 100pt)\n#let unused = [\n  Spans lines\n]\n";
+
+	fn error(world: &World) -> Span {
+		let result: SourceResult<PagedDocument> = world.compile().output;
+		result.expect_err("document must not compile")[0].span
+	}
+
+	#[test]
+	fn locates_code_after_preamble() {
+		let world = World::from_source_and_project(
+			PREAMBLE,
+			"= Title\n\n  #undefined-thing\n",
+			Project::default(),
+		);
+		let location = world.locate(error(&world)).unwrap();
+		assert_eq!(location.path, None);
+		assert_eq!((location.line, location.column), (3, 4));
+		assert_eq!(location.excerpt, "  #undefined-thing\n   ^^^^^^^^^^^^^^^");
+	}
+
+	#[test]
+	fn locates_project_files() {
+		let mut project = Project::default();
+		project.insert("lib.typ", b"#let x = 1\n\t#x.missing".to_vec()).unwrap();
+		let world = World::from_source_and_project(PREAMBLE, "#include \"lib.typ\"", project);
+		let location = world.locate(error(&world)).unwrap();
+		assert_eq!(location.path.as_deref(), Some("/lib.typ"));
+		assert_eq!((location.line, location.column), (2, 5));
+		assert_eq!(location.excerpt, "\t#x.missing\n\t   ^^^^^^^");
+	}
+
+	#[test]
+	fn ignores_preamble() {
+		let world =
+			World::from_source_and_project("#undefined-thing\n", "Hello", Project::default());
+		assert!(world.locate(error(&world)).is_none());
+	}
+}
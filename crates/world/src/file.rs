@@ -1,17 +1,34 @@
+use std::sync::OnceLock;
 use typst::{
+	diag::{FileError, FileResult},
 	foundations::Bytes,
 	syntax::{FileId, Source},
 };
 
 pub struct File {
+	id: FileId,
 	pub bytes: Bytes,
-	pub source: Source,
+	source: OnceLock<FileResult<Source>>,
 }
 
 impl File {
-	pub fn new(id: FileId, text: String) -> Self {
+	pub fn new(id: FileId, bytes: Bytes) -> Self {
+		Self { id, bytes, source: OnceLock::new() }
+	}
+
+	pub fn from_text(id: FileId, text: String) -> Self {
 		let bytes = Bytes::new(text.clone().into_bytes());
 		let source = Source::new(id, text);
-		Self { bytes, source }
+		Self { id, bytes, source: OnceLock::from(Ok(source)) }
+	}
+
+	/// Lazily decodes the file as a Typst source. Binary files (e.g., images) are never parsed.
+	pub fn source(&self) -> FileResult<Source> {
+		self.source
+			.get_or_init(|| {
+				let text = str::from_utf8(&self.bytes).map_err(|_| FileError::InvalidUtf8)?;
+				Ok(Source::new(self.id, text.into()))
+			})
+			.clone()
 	}
 }
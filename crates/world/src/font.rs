@@ -1,22 +1,76 @@
-use std::sync::LazyLock;
+use std::{
+	fs, io,
+	path::Path,
+	sync::{LazyLock, OnceLock},
+};
+use tracing::{info, warn};
 use ttf_parser::fonts_in_collection;
 use typst::{
 	foundations::Bytes,
 	text::{Font, FontBook},
 	utils::LazyHash,
 };
-use typst_assets::fonts;
 
 type Fonts = Box<[Font]>;
-pub static FONTS: LazyLock<Fonts> = LazyLock::new(|| {
-	fonts()
-		.flat_map(|bytes| {
-			let faces = fonts_in_collection(bytes).unwrap_or(1);
-			let bytes = Bytes::new(bytes);
-			(0..faces).flat_map(move |index| Font::new(bytes.clone(), index))
-		})
-		.collect()
-});
+
+/// Set by [`load_fonts`], or else only the embedded fonts are available.
+static FONTS: OnceLock<Fonts> = OnceLock::new();
 
 pub static FONT_BOOK: LazyLock<LazyHash<FontBook>> =
-	LazyLock::new(|| LazyHash::new(FontBook::from_fonts(FONTS.iter())));
+	LazyLock::new(|| LazyHash::new(FontBook::from_fonts(fonts().iter())));
+
+pub fn fonts() -> &'static Fonts {
+	FONTS.get_or_init(|| embedded_fonts().collect())
+}
+
+/// Adds the fonts in `dir` to the embedded ones. This must happen before the first compilation,
+/// so it has no effect if the fonts have already been used.
+pub fn load_fonts(dir: &Path) -> io::Result<()> {
+	let custom = read_fonts(dir)?;
+	info!(dir = %dir.display(), count = custom.len(), "custom fonts loaded");
+	if FONTS.set(embedded_fonts().chain(custom).collect()).is_err() {
+		warn!("fonts were already in use, ignoring custom fonts");
+	}
+	Ok(())
+}
+
+/// Lists the font families (in alphabetical order) without keeping the fonts in memory.
+pub fn font_families(dir: Option<&Path>) -> io::Result<Vec<String>> {
+	let custom = dir.map(read_fonts).transpose()?.unwrap_or_default();
+	let fonts = embedded_fonts().chain(custom).collect::<Vec<_>>();
+	let book = FontBook::from_fonts(&fonts);
+	Ok(book.families().map(|(family, _)| family.into()).collect())
+}
+
+fn embedded_fonts() -> impl Iterator<Item = Font> {
+	typst_assets::fonts().flat_map(|bytes| faces(Bytes::new(bytes)))
+}
+
+/// Reads every font (collection) directly inside `dir`. Files are sorted by name so that every
+/// process agrees on the order of the fonts.
+fn read_fonts(dir: &Path) -> io::Result<Vec<Font>> {
+	let mut paths = fs::read_dir(dir)?
+		.map(|entry| entry.map(|entry| entry.path()))
+		.collect::<io::Result<Vec<_>>>()?;
+	paths.sort_unstable();
+
+	let mut fonts = Vec::new();
+	for path in paths {
+		let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+		if !matches!(extension.as_deref(), Some("ttf" | "otf" | "ttc" | "otc")) {
+			continue;
+		}
+
+		let count = fonts.len();
+		fonts.extend(faces(Bytes::new(fs::read(&path)?)));
+		if fonts.len() == count {
+			warn!(path = %path.display(), "skipping invalid font file");
+		}
+	}
+	Ok(fonts)
+}
+
+fn faces(bytes: Bytes) -> impl Iterator<Item = Font> {
+	let count = fonts_in_collection(&bytes).unwrap_or(1);
+	(0..count).flat_map(move |index| Font::new(bytes.clone(), index))
+}
@@ -1,76 +1,291 @@
+mod diagnostic;
 mod file;
 mod font;
 mod library;
+mod package;
+mod project;
 
-use bytemuck::cast_slice;
-use ecow::EcoVec;
+use core::time::Duration;
 use file::File;
-use font::{FONT_BOOK, FONTS};
+use font::{FONT_BOOK, fonts};
 use image::{ColorType, ImageFormat, write_buffer_with_format};
 use library::LIBRARY;
 use std::collections::BTreeMap;
 use std::io::Cursor;
-use time::{PrimitiveDateTime, UtcDateTime, UtcOffset};
+use std::sync::{LazyLock, Mutex};
+use time::{PrimitiveDateTime, UtcDateTime};
 use typst::{
 	Document, Library, World as TypstWorld, compile,
-	diag::{FileError, FileResult, SourceResult},
-	foundations::{Bytes, Datetime},
-	layout::{Abs, PagedDocument},
+	diag::{FileError, FileResult, PackageError, SourceResult},
+	foundations::{Bytes, Datetime, Dict, Value},
+	layout::Abs,
 	syntax::{FileId, Source, VirtualPath},
 	text::{Font, FontBook},
 	utils::LazyHash,
 };
-use typst_render::render_merged;
+use typst_render::{render, render_merged};
 
+/// The default resolution of raster images.
+pub const DEFAULT_PIXEL_PER_PT: f32 = 4.;
+
+pub use font::{font_families, load_fonts};
+pub use package::{PackageStore, parse_allowlist};
+pub use project::{MAX_PROJECT_FILES, MAX_PROJECT_SIZE, Project, ProjectError};
+pub use time::UtcOffset;
+pub use typscord_protocol::{Format, Location};
 pub use typst::diag::{SourceDiagnostic, Warned};
+pub use typst::layout::PagedDocument;
+pub use typst::syntax::Span;
+pub use typst::syntax::package::PackageSpec;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Pages {
+	/// Stitches all pages into a single file.
+	#[default]
+	Merged,
+	/// Renders each page as its own file, but only up to the given number of pages.
+	Split(usize),
+}
 
-type Diagnostics = EcoVec<SourceDiagnostic>;
-pub struct Render {
-	pub document: PagedDocument,
-	pub buffer: Vec<u8>,
+#[derive(Clone, Copy, Debug)]
+pub struct RenderOptions {
+	pub format: Format,
+	/// Ignored for PDFs since they are multi-page documents anyway.
+	pub pages: Pages,
+	/// Only relevant to [raster](Format::is_raster) formats.
+	pub pixel_per_pt: f32,
+}
+
+impl Default for RenderOptions {
+	fn default() -> Self {
+		Self {
+			format: Format::default(),
+			pages: Pages::default(),
+			pixel_per_pt: DEFAULT_PIXEL_PER_PT,
+		}
+	}
 }
 
 pub struct World {
+	main: FileId,
+	/// Number of lines that the preamble prepends to the entry point.
+	preamble_lines: usize,
+	/// Only documents with `sys.inputs` (i.e., every render of the server) have their own library.
+	/// Otherwise, the shared [`LIBRARY`] is used.
+	library: Option<LazyHash<Library>>,
+	/// `datetime.today()` is pinned to this instant so that re-renders are reproducible.
+	now: UtcDateTime,
+	/// Used by `datetime.today()` unless the document asks for a specific offset.
+	offset: UtcOffset,
 	sources: BTreeMap<FileId, File>,
+	packages: Option<PackageStore>,
+	/// Package files that have already been loaded from the [`PackageStore`].
+	package_files: Mutex<BTreeMap<FileId, File>>,
+}
+
+/// Forces the lazily loaded fonts and compiles a throwaway document so that the first compilation
+/// does not have to pay for them. Every render has its own `sys.inputs` (and thus library), so the
+/// shared [`LIBRARY`] would not be reused anyway.
+pub fn warm_up() {
+	LazyLock::force(&FONT_BOOK);
+	let world = World::from_single_source("").with_inputs([(String::new(), String::new())]);
+	let _ = world.compile::<PagedDocument>();
 }
 
 impl World {
-	pub fn from_single_source(contents: String) -> Self {
-		// Entry point is basically a single file named `main.typ`
-		let entry_file_id = FileId::new_fake(VirtualPath::new("/main.typ"));
-		let entry_source = File::new(entry_file_id, contents);
-		Self { sources: BTreeMap::from([(entry_file_id, entry_source)]) }
+	pub fn from_single_source(contents: &str) -> Self {
+		Self::from_source_and_project("", contents, Project::default())
+	}
+
+	/// The `preamble` followed by the `code` becomes a synthetic entry point that may import any
+	/// file in the project.
+	pub fn from_source_and_project(preamble: &str, code: &str, project: Project) -> Self {
+		// Entry point is basically a file named `main.typ` that is separate from the project
+		let main = FileId::new_fake(VirtualPath::new("/main.typ"));
+		let mut world = Self::from_files(main, preamble, project);
+
+		let mut contents = String::from(preamble);
+		contents.push_str(code);
+		world.sources.insert(main, File::from_text(main, contents));
+		world
+	}
+
+	/// Compiles the `entry` file of the project. The `preamble` is prepended to the entry point.
+	pub fn from_project(
+		project: Project,
+		entry: &str,
+		preamble: &str,
+	) -> Result<Self, ProjectError> {
+		let path =
+			project::normalize(entry).ok_or_else(|| ProjectError::InvalidPath(entry.into()))?;
+		let main = FileId::new(None, VirtualPath::new(&path));
+		let mut world = Self::from_files(main, preamble, project);
+
+		let File { bytes, .. } = world
+			.sources
+			.get(&main)
+			.ok_or_else(|| ProjectError::MissingEntry(path.as_str().into()))?;
+		let text =
+			str::from_utf8(bytes).map_err(|_| ProjectError::InvalidEntry(path.as_str().into()))?;
+
+		let mut contents = String::from(preamble);
+		contents.push_str(text);
+		world.sources.insert(main, File::from_text(main, contents));
+		Ok(world)
+	}
+
+	fn from_files(main: FileId, preamble: &str, Project { files, .. }: Project) -> Self {
+		let sources = files
+			.into_iter()
+			.map(|(path, bytes)| {
+				let id = FileId::new(None, VirtualPath::new(&*path));
+				(id, File::new(id, Bytes::new(bytes)))
+			})
+			.collect();
+		Self {
+			main,
+			preamble_lines: preamble.lines().count(),
+			library: None,
+			now: UtcDateTime::now(),
+			offset: UtcOffset::UTC,
+			sources,
+			packages: None,
+			package_files: Mutex::default(),
+		}
+	}
+
+	/// Pins the clock to the given time since the Unix epoch.
+	pub fn with_clock(mut self, timestamp: Duration, offset: UtcOffset) -> Self {
+		self.now = UtcDateTime::UNIX_EPOCH + timestamp;
+		self.offset = offset;
+		self
+	}
+
+	pub fn with_inputs(mut self, inputs: impl IntoIterator<Item = (String, String)>) -> Self {
+		let inputs = inputs
+			.into_iter()
+			.map(|(key, value)| (key.into(), Value::Str(value.into())))
+			.collect::<Dict>();
+		if !inputs.is_empty() {
+			self.library = Some(library::with_inputs(inputs));
+		}
+		self
+	}
+
+	pub fn with_package_store(mut self, packages: PackageStore) -> Self {
+		self.packages = Some(packages);
+		self
+	}
+
+	fn lookup<T>(&self, id: FileId, f: impl FnOnce(&File) -> FileResult<T>) -> FileResult<T> {
+		if let Some(file) = self.sources.get(&id) {
+			return f(file);
+		}
+
+		let Some(spec) = id.package() else {
+			return Err(FileError::NotFound(id.vpath().as_rooted_path().into()));
+		};
+
+		let packages = self
+			.packages
+			.as_ref()
+			.ok_or_else(|| FileError::Package(PackageError::NotFound(spec.clone())))?;
+
+		let mut package_files =
+			self.package_files.lock().expect("package cache must not be poisoned");
+		if let Some(file) = package_files.get(&id) {
+			return f(file);
+		}
+
+		let file = File::new(id, packages.read(spec, id.vpath())?);
+		f(package_files.entry(id).or_insert(file))
 	}
 
 	pub fn compile<D: Document>(&self) -> Warned<SourceResult<D>> {
 		compile(self)
 	}
+}
 
-	pub fn render(&self) -> Warned<Result<Render, Diagnostics>> {
-		let Warned { output, warnings } = self.compile::<PagedDocument>();
-		Warned {
-			warnings,
-			output: output.map(|document| {
-				let pixel_map = render_merged(&document, 4., Abs::zero(), None);
-				let mut buffer = Cursor::<Vec<_>>::default();
-				write_buffer_with_format(
-					&mut buffer,
-					cast_slice(pixel_map.pixels()),
-					pixel_map.width(),
-					pixel_map.height(),
-					ColorType::Rgba8,
-					ImageFormat::WebP,
-				)
-				.expect("writing to Vec must be infallible");
-				Render { document, buffer: buffer.into_inner() }
-			}),
+/// Exports an already compiled document. This is useful for re-exporting the same document with
+/// different options (e.g., a lower resolution) without recompiling.
+pub fn export(
+	document: &PagedDocument,
+	RenderOptions { format, pages, pixel_per_pt }: RenderOptions,
+) -> SourceResult<Vec<Vec<u8>>> {
+	let image_format = match format {
+		Format::WebP => Some(ImageFormat::WebP),
+		Format::Png => Some(ImageFormat::Png),
+		Format::Svg => None,
+		Format::Pdf => return Ok(vec![export_pdf(document)?]),
+	};
+
+	Ok(match pages {
+		Pages::Merged => vec![match image_format {
+			Some(image_format) => {
+				let mut pixel_map = render_merged(document, pixel_per_pt, Abs::zero(), None);
+				demultiply(pixel_map.data_mut());
+				encode_image(pixel_map.data(), pixel_map.width(), pixel_map.height(), image_format)
+			}
+			None => typst_svg::svg_merged(document, Abs::zero()).into_bytes(),
+		}],
+		Pages::Split(limit) => document
+			.pages
+			.iter()
+			.take(limit)
+			.map(|page| match image_format {
+				Some(image_format) => {
+					let mut pixel_map = render(page, pixel_per_pt);
+					demultiply(pixel_map.data_mut());
+					encode_image(
+						pixel_map.data(),
+						pixel_map.width(),
+						pixel_map.height(),
+						image_format,
+					)
+				}
+				None => typst_svg::svg(page).into_bytes(),
+			})
+			.collect(),
+	})
+}
+
+/// The renderer premultiplies the color channels by the alpha channel, but the image encoders expect
+/// straight alpha. Otherwise, the edges of text on a transparent page come out too dark.
+fn demultiply(data: &mut [u8]) {
+	for pixel in data.chunks_exact_mut(4) {
+		let alpha = u16::from(pixel[3]);
+		if alpha == 0 || alpha == 255 {
+			continue;
+		}
+		for channel in &mut pixel[..3] {
+			*channel = ((u16::from(*channel) * 255 + alpha / 2) / alpha).min(255) as u8;
 		}
 	}
 }
 
+fn encode_image(pixels: &[u8], width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+	let mut buffer = Cursor::<Vec<_>>::default();
+	write_buffer_with_format(&mut buffer, pixels, width, height, ColorType::Rgba8, format)
+		.expect("writing to Vec must be infallible");
+	buffer.into_inner()
+}
+
+#[cfg(feature = "pdf")]
+fn export_pdf(document: &PagedDocument) -> SourceResult<Vec<u8>> {
+	typst_pdf::pdf(document, &typst_pdf::PdfOptions::default())
+}
+
+#[cfg(not(feature = "pdf"))]
+fn export_pdf(_: &PagedDocument) -> SourceResult<Vec<u8>> {
+	typst::diag::bail!(
+		typst::syntax::Span::detached(),
+		"PDF output is not available on this server"
+	)
+}
+
 impl TypstWorld for World {
 	fn library(&self) -> &LazyHash<Library> {
-		&LIBRARY
+		self.library.as_ref().unwrap_or(&LIBRARY)
 	}
 
 	fn book(&self) -> &LazyHash<FontBook> {
@@ -78,35 +293,29 @@ impl TypstWorld for World {
 	}
 
 	fn font(&self, index: usize) -> Option<Font> {
-		FONTS.get(index).cloned()
+		fonts().get(index).cloned()
 	}
 
 	fn today(&self, offset: Option<i64>) -> Option<Datetime> {
-		let now = UtcDateTime::now();
-		let offset = offset
-			.and_then(|offset| {
-				let offset = offset.try_into().ok()?;
-				UtcOffset::from_hms(offset, 0, 0).ok()
-			})
-			.unwrap_or(UtcOffset::UTC);
-		let now = now.to_offset(offset);
+		// Typst only accepts whole hours, but the default offset may be any number of minutes.
+		// Out-of-range offsets are reported as an error instead of falling back to UTC.
+		let offset = match offset {
+			Some(hours) => UtcOffset::from_hms(hours.try_into().ok()?, 0, 0).ok()?,
+			None => self.offset,
+		};
+		let now = self.now.to_offset(offset);
 		Some(Datetime::Datetime(PrimitiveDateTime::new(now.date(), now.time())))
 	}
 
 	fn main(&self) -> FileId {
-		let (&id, _) = self.sources.first_key_value().expect("root source must be present");
-		id
+		self.main
 	}
 
 	fn source(&self, id: FileId) -> FileResult<Source> {
-		// TODO: Support external packages.
-		let File { source, .. } = self.sources.get(&id).ok_or(FileError::NotSource)?;
-		Ok(source.clone())
+		self.lookup(id, File::source)
 	}
 
 	fn file(&self, id: FileId) -> FileResult<Bytes> {
-		// TODO: Support external packages.
-		let File { bytes, .. } = self.sources.get(&id).ok_or(FileError::NotSource)?;
-		Ok(bytes.clone())
+		self.lookup(id, |File { bytes, .. }| Ok(bytes.clone()))
 	}
 }
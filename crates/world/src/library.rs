@@ -1,5 +1,11 @@
 use std::sync::LazyLock;
-use typst::{Library, LibraryExt as _, utils::LazyHash};
+use typst::{Library, LibraryExt as _, foundations::Dict, utils::LazyHash};
 
+/// Shared by all documents without `sys.inputs`.
 pub static LIBRARY: LazyLock<LazyHash<Library>> =
 	LazyLock::new(|| LazyHash::new(Library::default()));
+
+/// A fresh library is needed for every set of `sys.inputs`.
+pub fn with_inputs(inputs: Dict) -> LazyHash<Library> {
+	LazyHash::new(Library::builder().with_inputs(inputs).build())
+}
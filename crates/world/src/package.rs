@@ -0,0 +1,102 @@
+use ecow::{EcoString, eco_format};
+use std::{collections::HashSet, fs, path::Path};
+use typst::{
+	diag::{FileError, FileResult, PackageError},
+	foundations::Bytes,
+	syntax::{VirtualPath, package::PackageSpec},
+};
+
+/// A read-only package directory with the same `{namespace}/{name}/{version}` layout as the
+/// Typst package cache. Only allowlisted packages can be resolved, so the worker never needs to
+/// touch the network.
+pub struct PackageStore {
+	root: Box<Path>,
+	allowlist: HashSet<PackageSpec>,
+}
+
+/// Parses a comma-separated list of package specifications (e.g., `@preview/cetz:0.4.2`). Blank
+/// entries are skipped.
+pub fn parse_allowlist(list: &str) -> Result<HashSet<PackageSpec>, EcoString> {
+	list.split(',')
+		.map(str::trim)
+		.filter(|spec| !spec.is_empty())
+		.map(|spec| spec.parse().map_err(|error| eco_format!("`{spec}` is invalid: {error}")))
+		.collect()
+}
+
+impl PackageStore {
+	pub fn new(root: Box<Path>, allowlist: HashSet<PackageSpec>) -> Self {
+		Self { root, allowlist }
+	}
+
+	pub fn read(&self, spec: &PackageSpec, vpath: &VirtualPath) -> FileResult<Bytes> {
+		if !self.allowlist.contains(spec) {
+			return Err(FileError::Package(PackageError::Other(Some(eco_format!(
+				"{spec} is not an approved package"
+			)))));
+		}
+
+		let package_root = self
+			.root
+			.join(spec.namespace.as_str())
+			.join(spec.name.as_str())
+			.join(spec.version.to_string());
+		if !package_root.is_dir() {
+			return Err(FileError::Package(PackageError::NotFound(spec.clone())));
+		}
+
+		// Rejects paths that attempt to escape the package root
+		let path = vpath.resolve(&package_root).ok_or(FileError::AccessDenied)?;
+		let bytes = fs::read(&path).map_err(|error| FileError::from_io(error, &path))?;
+		Ok(Bytes::new(bytes))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{env, process};
+
+	fn store() -> (Box<Path>, PackageStore) {
+		let root = env::temp_dir().join(format!("typscord-packages-{}", process::id()));
+		let package = root.join("preview").join("approved").join("0.1.0");
+		fs::create_dir_all(&package).unwrap();
+		fs::write(package.join("lib.typ"), "#let x = 1").unwrap();
+		fs::write(root.join("secret.typ"), "#let secret = 1").unwrap();
+
+		let allowlist = parse_allowlist("@preview/approved:0.1.0, ").unwrap();
+		let root = root.into_boxed_path();
+		(root.clone(), PackageStore::new(root, allowlist))
+	}
+
+	#[test]
+	fn parses_allowlist() {
+		assert_eq!(parse_allowlist(" @preview/a:0.1.0 ,, @local/b:1.2.3 ").unwrap().len(), 2);
+		assert!(parse_allowlist("").unwrap().is_empty());
+		assert!(parse_allowlist("@preview/a:0.1.0,@preview/b").is_err());
+		assert!(parse_allowlist("preview/a:0.1.0").is_err());
+	}
+
+	#[test]
+	fn reads_approved_packages_only() {
+		let (root, packages) = store();
+		let lib = VirtualPath::new("lib.typ");
+
+		let approved = "@preview/approved:0.1.0".parse().unwrap();
+		assert_eq!(packages.read(&approved, &lib).unwrap().as_slice(), b"#let x = 1");
+
+		// Installed in the store but not on the allowlist
+		fs::create_dir_all(root.join("preview").join("other").join("0.1.0")).unwrap();
+		let other = "@preview/other:0.1.0".parse().unwrap();
+		assert!(matches!(
+			packages.read(&other, &lib),
+			Err(FileError::Package(PackageError::Other(_)))
+		));
+
+		// Escaping the package root (even into the store itself) is denied
+		let escape = VirtualPath::new("../../../secret.typ");
+		assert!(matches!(packages.read(&approved, &escape), Err(FileError::AccessDenied)));
+
+		fs::remove_dir_all(root).unwrap();
+	}
+}
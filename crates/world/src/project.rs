@@ -0,0 +1,278 @@
+use core::fmt;
+use std::{
+	collections::BTreeMap,
+	io::{Cursor, Read as _},
+};
+use zip::{ZipArchive, result::ZipError};
+
+/// Maximum number of files in a single project (including archive contents).
+pub const MAX_PROJECT_FILES: usize = 256;
+
+/// Maximum combined size of all (decompressed) files in a single project.
+pub const MAX_PROJECT_SIZE: u64 = 32 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum ProjectError {
+	/// The path is empty or escapes the project root.
+	InvalidPath(Box<str>),
+	DuplicatePath(Box<str>),
+	MissingEntry(Box<str>),
+	/// The entry point is not valid UTF-8.
+	InvalidEntry(Box<str>),
+	TooManyFiles,
+	TooLarge,
+	Archive(ZipError),
+}
+
+impl fmt::Display for ProjectError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::InvalidPath(path) => write!(f, "`{path}` is not a valid project path"),
+			Self::DuplicatePath(path) => {
+				write!(f, "`{path}` appears more than once in the project")
+			}
+			Self::MissingEntry(path) => {
+				write!(f, "entry point `{path}` does not exist in the project")
+			}
+			Self::InvalidEntry(path) => {
+				write!(f, "entry point `{path}` is not a Typst source file")
+			}
+			Self::TooManyFiles => {
+				write!(f, "projects are limited to {MAX_PROJECT_FILES} files")
+			}
+			Self::TooLarge => {
+				write!(f, "projects are limited to {} MiB in total", MAX_PROJECT_SIZE >> 20)
+			}
+			Self::Archive(error) => write!(f, "malformed archive: {error}"),
+		}
+	}
+}
+
+impl core::error::Error for ProjectError {}
+
+/// A tree of files keyed by their normalized (rooted) paths.
+#[derive(Default)]
+pub struct Project {
+	pub(crate) files: BTreeMap<Box<str>, Vec<u8>>,
+	size: u64,
+}
+
+impl Project {
+	pub fn insert(&mut self, path: &str, bytes: Vec<u8>) -> Result<(), ProjectError> {
+		let path = normalize(path).ok_or_else(|| ProjectError::InvalidPath(path.into()))?;
+
+		if self.files.contains_key(&*path) {
+			return Err(ProjectError::DuplicatePath(path.into()));
+		}
+
+		if self.files.len() >= MAX_PROJECT_FILES {
+			return Err(ProjectError::TooManyFiles);
+		}
+
+		// Rejected files do not count towards the limit
+		let size = self.size + bytes.len() as u64;
+		if size > MAX_PROJECT_SIZE {
+			return Err(ProjectError::TooLarge);
+		}
+
+		self.size = size;
+		self.files.insert(path.into(), bytes);
+		Ok(())
+	}
+
+	/// Extracts every file in the `.zip` archive relative to the project root. Directories are
+	/// implied by the file paths, so they are skipped.
+	pub fn extract_zip(&mut self, bytes: &[u8]) -> Result<(), ProjectError> {
+		let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(ProjectError::Archive)?;
+		for index in 0..archive.len() {
+			let entry = archive.by_index(index).map_err(ProjectError::Archive)?;
+			if entry.is_dir() {
+				continue;
+			}
+
+			// The declared size cannot be trusted, so the decompression itself is also capped.
+			let remaining = MAX_PROJECT_SIZE.saturating_sub(self.size);
+			if entry.size() > remaining {
+				return Err(ProjectError::TooLarge);
+			}
+
+			let path = entry.name().to_owned();
+			let mut contents = Vec::new();
+			entry
+				.take(remaining + 1)
+				.read_to_end(&mut contents)
+				.map_err(|error| ProjectError::Archive(error.into()))?;
+			self.insert(&path, contents)?;
+		}
+		Ok(())
+	}
+}
+
+/// Normalizes a relative or rooted path into a rooted path with `/` separators. Returns [`None`]
+/// for empty paths and paths that attempt to escape the project root via `..`.
+pub fn normalize(path: &str) -> Option<String> {
+	let mut components = Vec::new();
+	for component in path.split(['/', '\\']) {
+		match component {
+			"" | "." => continue,
+			".." => {
+				components.pop()?;
+			}
+			component => components.push(component),
+		}
+	}
+
+	if components.is_empty() {
+		return None;
+	}
+
+	let mut normalized = String::with_capacity(path.len() + 1);
+	for component in components {
+		normalized.push('/');
+		normalized.push_str(component);
+	}
+	Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Write as _;
+	use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+	fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+		let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+		let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+		for (name, contents) in entries {
+			writer.start_file(*name, options).unwrap();
+			writer.write_all(contents).unwrap();
+		}
+		writer.finish().unwrap().into_inner()
+	}
+
+	#[test]
+	fn normalizes_paths() {
+		assert_eq!(normalize("main.typ").as_deref(), Some("/main.typ"));
+		assert_eq!(normalize("./a/./b.typ").as_deref(), Some("/a/b.typ"));
+		assert_eq!(normalize("a//b.typ").as_deref(), Some("/a/b.typ"));
+		assert_eq!(normalize("a/b/../c.typ").as_deref(), Some("/a/c.typ"));
+		assert_eq!(normalize("a\\b.typ").as_deref(), Some("/a/b.typ"));
+
+		// Absolute paths are confined to the project root
+		assert_eq!(normalize("/etc/passwd").as_deref(), Some("/etc/passwd"));
+		assert_eq!(normalize("C:\\Windows\\win.ini").as_deref(), Some("/C:/Windows/win.ini"));
+	}
+
+	#[test]
+	fn rejects_escaping_paths() {
+		for path in
+			["", "/", ".", "..", "../main.typ", "a/../../main.typ", "..\\main.typ", "a\\..\\.."]
+		{
+			assert_eq!(normalize(path), None, "{path:?} must be rejected");
+		}
+
+		let mut project = Project::default();
+		let error = project.insert("../secret.typ", Vec::new()).unwrap_err();
+		assert!(matches!(error, ProjectError::InvalidPath(_)));
+	}
+
+	#[test]
+	fn rejects_duplicate_paths() {
+		let mut project = Project::default();
+		project.insert("a/b.typ", Vec::new()).unwrap();
+		let error = project.insert("./a\\b.typ", Vec::new()).unwrap_err();
+		assert!(matches!(error, ProjectError::DuplicatePath(path) if &*path == "/a/b.typ"));
+	}
+
+	#[test]
+	fn rejected_files_take_no_space() {
+		let mut project = Project::default();
+		project.insert("half.bin", vec![0; MAX_PROJECT_SIZE as usize / 2]).unwrap();
+		let duplicate = project.insert("half.bin", vec![0; MAX_PROJECT_SIZE as usize / 2]);
+		assert!(matches!(duplicate, Err(ProjectError::DuplicatePath(_))));
+		let too_large = project.insert("full.bin", vec![0; MAX_PROJECT_SIZE as usize]);
+		assert!(matches!(too_large, Err(ProjectError::TooLarge)));
+		project.insert("other-half.bin", vec![0; MAX_PROJECT_SIZE as usize / 2]).unwrap();
+	}
+
+	#[test]
+	fn limits_file_count() {
+		let mut project = Project::default();
+		for index in 0..MAX_PROJECT_FILES {
+			project.insert(&format!("{index}.typ"), Vec::new()).unwrap();
+		}
+		let error = project.insert("one-too-many.typ", Vec::new()).unwrap_err();
+		assert!(matches!(error, ProjectError::TooManyFiles));
+	}
+
+	#[test]
+	fn limits_total_size() {
+		let mut project = Project::default();
+		project.insert("half.bin", vec![0; MAX_PROJECT_SIZE as usize / 2]).unwrap();
+		project.insert("other-half.bin", vec![0; MAX_PROJECT_SIZE as usize / 2]).unwrap();
+		let error = project.insert("byte.bin", vec![0]).unwrap_err();
+		assert!(matches!(error, ProjectError::TooLarge));
+	}
+
+	#[test]
+	fn extracts_archives() {
+		let archive = zip(&[("main.typ", b"#import \"lib/util.typ\""), ("lib/util.typ", b"")]);
+		let mut project = Project::default();
+		project.extract_zip(&archive).unwrap();
+		assert_eq!(
+			project.files.keys().map(AsRef::as_ref).collect::<Vec<_>>(),
+			["/lib/util.typ", "/main.typ"]
+		);
+	}
+
+	#[test]
+	fn rejects_escaping_archive_entries() {
+		let archive = zip(&[("../../escape.typ", b"")]);
+		let error = Project::default().extract_zip(&archive).unwrap_err();
+		assert!(matches!(error, ProjectError::InvalidPath(_)));
+	}
+
+	#[test]
+	fn limits_archive_files() {
+		let names = (0..=MAX_PROJECT_FILES).map(|index| format!("{index}.typ")).collect::<Vec<_>>();
+		let entries = names.iter().map(|name| (name.as_str(), &b""[..])).collect::<Vec<_>>();
+		let error = Project::default().extract_zip(&zip(&entries)).unwrap_err();
+		assert!(matches!(error, ProjectError::TooManyFiles));
+	}
+
+	#[test]
+	fn limits_decompressed_size() {
+		// Zeros compress extremely well, so the archive itself is tiny
+		let bomb = vec![0; MAX_PROJECT_SIZE as usize + 1];
+		let archive = zip(&[("bomb.bin", &bomb)]);
+		assert!(archive.len() < 1024 * 1024);
+
+		let error = Project::default().extract_zip(&archive).unwrap_err();
+		assert!(matches!(error, ProjectError::TooLarge));
+	}
+
+	#[test]
+	fn distrusts_declared_sizes() {
+		let bomb = vec![0; MAX_PROJECT_SIZE as usize + 1];
+		let mut archive = zip(&[("bomb.bin", &bomb)]);
+
+		// Claim that the entry is tiny in both the local and the central directory headers
+		let declared = (MAX_PROJECT_SIZE as u32 + 1).to_le_bytes();
+		let offsets = archive
+			.windows(4)
+			.enumerate()
+			.filter(|&(_, window)| window == declared)
+			.map(|(offset, _)| offset)
+			.collect::<Vec<_>>();
+		assert_eq!(offsets.len(), 2);
+		for offset in offsets {
+			archive[offset..offset + 4].copy_from_slice(&16u32.to_le_bytes());
+		}
+
+		let mut project = Project::default();
+		let error = project.extract_zip(&archive).unwrap_err();
+		// Caught by the capped decompression rather than the declared size
+		assert!(matches!(error, ProjectError::TooLarge));
+		assert!(project.files.is_empty());
+	}
+}
@@ -1,3 +1,6 @@
+mod replay;
+#[cfg(target_os = "linux")]
+mod sandbox;
 mod web;
 mod worker;
 
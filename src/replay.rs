@@ -0,0 +1,110 @@
+use core::time::Duration;
+use std::{
+	collections::VecDeque,
+	sync::Mutex,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Number of recent interactions remembered for detecting replays.
+const SEEN_CAPACITY: usize = 4096;
+
+/// Rejects signed requests that are stale or have been seen before. Interactions only need to be
+/// remembered while their timestamp is fresh since older replays are already stale.
+pub struct ReplayGuard {
+	max_skew: Duration,
+	/// The IDs and signature timestamps of recent interactions (oldest first).
+	seen: Mutex<VecDeque<(u64, u64)>>,
+}
+
+impl ReplayGuard {
+	pub const fn new(max_skew: Duration) -> Self {
+		Self { max_skew, seen: Mutex::new(VecDeque::new()) }
+	}
+
+	/// Whether the Unix timestamp (in seconds) is within the skew window around the current time.
+	pub fn is_fresh(&self, timestamp: u64) -> bool {
+		now().abs_diff(Duration::from_secs(timestamp)) <= self.max_skew
+	}
+
+	/// Remembers the interaction. Returns `false` if it has been seen before.
+	pub fn witness(&self, id: u64, timestamp: u64) -> bool {
+		let now = now();
+		let mut seen = self.seen.lock().expect("replay guard must not be poisoned");
+		seen.retain(|&(_, seen_at)| now.abs_diff(Duration::from_secs(seen_at)) <= self.max_skew);
+
+		if seen.iter().any(|&(seen_id, _)| seen_id == id) {
+			return false;
+		}
+		if seen.len() >= SEEN_CAPACITY {
+			seen.pop_front();
+		}
+		seen.push_back((id, timestamp));
+		true
+	}
+}
+
+fn now() -> Duration {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const MAX_SKEW: Duration = Duration::from_secs(60);
+
+	fn timestamp() -> u64 {
+		now().as_secs()
+	}
+
+	#[test]
+	fn rejects_stale_timestamps() {
+		let guard = ReplayGuard::new(MAX_SKEW);
+		let now = timestamp();
+		assert!(guard.is_fresh(now));
+		assert!(guard.is_fresh(now - 30));
+		assert!(guard.is_fresh(now + 30), "slightly fast clocks must be tolerated");
+		assert!(!guard.is_fresh(now - 120));
+		assert!(!guard.is_fresh(now + 120));
+		assert!(!guard.is_fresh(0));
+	}
+
+	#[test]
+	fn rejects_duplicates() {
+		let guard = ReplayGuard::new(MAX_SKEW);
+		let now = timestamp();
+		assert!(guard.witness(1, now));
+		assert!(guard.witness(2, now));
+		assert!(!guard.witness(1, now));
+		assert!(!guard.witness(1, now - 10), "replays must be caught regardless of the timestamp");
+	}
+
+	#[test]
+	fn forgets_stale_interactions() {
+		let guard = ReplayGuard::new(MAX_SKEW);
+		let now = timestamp();
+		assert!(guard.witness(1, now - 120));
+		assert!(guard.witness(2, now));
+
+		// Replays of the first interaction are rejected as stale before they get here
+		assert!(guard.witness(3, now));
+		let seen = guard.seen.lock().unwrap();
+		assert_eq!(seen.iter().map(|&(id, _)| id).collect::<Vec<_>>(), [2, 3]);
+	}
+
+	#[test]
+	fn bounded_by_capacity() {
+		let guard = ReplayGuard::new(MAX_SKEW);
+		let now = timestamp();
+		for id in 0..SEEN_CAPACITY as u64 {
+			assert!(guard.witness(id, now));
+		}
+		assert!(!guard.witness(0, now));
+
+		// The oldest interaction makes room for the newest one
+		assert!(guard.witness(SEEN_CAPACITY as u64, now));
+		assert_eq!(guard.seen.lock().unwrap().len(), SEEN_CAPACITY);
+		assert!(!guard.witness(1, now));
+		assert!(!guard.witness(SEEN_CAPACITY as u64, now));
+	}
+}
@@ -0,0 +1,159 @@
+//! Confines the worker process before it reads any untrusted input. Nothing in here can be undone
+//! by the worker, and the restrictions are inherited by every thread spawned afterwards.
+
+use anyhow::{Context as _, Result};
+use seccompiler::{
+	BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition, SeccompFilter,
+	SeccompRule,
+};
+use std::{collections::BTreeMap, env, io};
+use tracing::info;
+
+#[cfg(target_env = "gnu")]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(not(target_env = "gnu"))]
+type Resource = libc::c_int;
+
+/// Memory in MiB kept aside for the server itself when the machine's memory is split among the
+/// workers.
+const SERVER_MEMORY_RESERVE: u64 = 256;
+
+/// Smallest address-space limit in MiB with which a worker can still compile simple documents.
+/// This includes the reserved (but unused) virtual memory of every thread.
+const MIN_MEMORY_LIMIT: u64 = 128;
+
+/// Default CPU time limit in seconds (summed across all threads).
+const DEFAULT_CPU_LIMIT: u64 = 10;
+
+/// Everything the standard library, the allocator, `rayon`, and `tracing` need at runtime.
+const SYSCALLS: &[libc::c_long] = &[
+	libc::SYS_brk,
+	libc::SYS_clock_gettime,
+	libc::SYS_clock_nanosleep,
+	libc::SYS_clone,
+	libc::SYS_clone3,
+	libc::SYS_close,
+	libc::SYS_exit,
+	libc::SYS_exit_group,
+	libc::SYS_fstat,
+	libc::SYS_futex,
+	libc::SYS_getdents64,
+	libc::SYS_getpid,
+	libc::SYS_getrandom,
+	libc::SYS_gettid,
+	libc::SYS_lseek,
+	libc::SYS_madvise,
+	libc::SYS_mmap,
+	libc::SYS_mprotect,
+	libc::SYS_mremap,
+	libc::SYS_munmap,
+	libc::SYS_nanosleep,
+	libc::SYS_newfstatat,
+	libc::SYS_pread64,
+	libc::SYS_read,
+	libc::SYS_readv,
+	libc::SYS_rseq,
+	libc::SYS_rt_sigaction,
+	libc::SYS_rt_sigprocmask,
+	libc::SYS_rt_sigreturn,
+	libc::SYS_sched_getaffinity,
+	libc::SYS_sched_yield,
+	libc::SYS_set_robust_list,
+	libc::SYS_sigaltstack,
+	libc::SYS_statx,
+	libc::SYS_tgkill,
+	libc::SYS_write,
+	libc::SYS_writev,
+];
+
+/// Applies the resource limits, no-new-privileges, and the system call allowlist.
+pub fn apply() -> Result<()> {
+	// The server always passes the limit on (see `memory_limit`)
+	let memory_limit: u64 = env::var("TYPSCORD_WORKER_MEMORY_LIMIT")
+		.context("TYPSCORD_WORKER_MEMORY_LIMIT must be set")?
+		.parse()
+		.context("TYPSCORD_WORKER_MEMORY_LIMIT must be a positive integer")?;
+	let cpu_limit = limit_from_env("TYPSCORD_WORKER_CPU_LIMIT", DEFAULT_CPU_LIMIT)?;
+
+	// Allocations beyond the limit fail (and abort the worker) instead of exhausting the host
+	set_rlimit(libc::RLIMIT_AS, memory_limit << 20, memory_limit << 20)?;
+
+	// The kernel sends `SIGXCPU` at the soft limit and `SIGKILL` one second later
+	set_rlimit(libc::RLIMIT_CPU, cpu_limit, cpu_limit + 1)?;
+
+	// Aborted workers should not leave core dumps of the user's input behind
+	set_rlimit(libc::RLIMIT_CORE, 0, 0)?;
+
+	info!(memory_limit, cpu_limit, "resource limits applied");
+	seccomp()
+}
+
+/// Splits the physical memory of the machine (minus the share of the server) evenly among the
+/// `workers` that may exist at once, so that they cannot exhaust it even when all of them hit
+/// their address-space limit together. The result is in MiB.
+pub fn memory_limit(workers: usize) -> Result<u64> {
+	// SAFETY: `sysconf` does not touch any memory.
+	let (pages, page_size) =
+		unsafe { (libc::sysconf(libc::_SC_PHYS_PAGES), libc::sysconf(libc::_SC_PAGESIZE)) };
+	let (Ok(pages), Ok(page_size)) = (u64::try_from(pages), u64::try_from(page_size)) else {
+		return Err(io::Error::last_os_error()).context("failed to query the physical memory");
+	};
+
+	let total = (pages * page_size) >> 20;
+	let limit = total.saturating_sub(SERVER_MEMORY_RESERVE) / workers.max(1) as u64;
+	anyhow::ensure!(
+		limit >= MIN_MEMORY_LIMIT,
+		"{total} MiB of memory is not enough for {workers} workers, so lower \
+		 TYPSCORD_MAX_CONCURRENT_RENDERS or TYPSCORD_WORKER_POOL_SIZE",
+	);
+	Ok(limit)
+}
+
+fn limit_from_env(name: &str, default: u64) -> Result<u64> {
+	match env::var(name) {
+		Ok(limit) => limit.parse().with_context(|| format!("{name} must be a positive integer")),
+		Err(env::VarError::NotPresent) => Ok(default),
+		Err(error) => Err(error).with_context(|| format!("{name} must be valid unicode")),
+	}
+}
+
+fn set_rlimit(resource: Resource, soft: u64, hard: u64) -> Result<()> {
+	let limit = libc::rlimit { rlim_cur: soft, rlim_max: hard };
+	// SAFETY: The pointer is valid for the duration of the call.
+	if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+		return Err(io::Error::last_os_error()).context("failed to set resource limit");
+	}
+	Ok(())
+}
+
+fn seccomp() -> Result<()> {
+	// SAFETY: `prctl` with `PR_SET_NO_NEW_PRIVS` does not touch any memory.
+	if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+		return Err(io::Error::last_os_error()).context("failed to set no-new-privileges");
+	}
+
+	// Files (e.g., packages) may only be opened for reading
+	let read_only = SeccompCondition::new(
+		2,
+		SeccompCmpArgLen::Dword,
+		SeccompCmpOp::MaskedEq((libc::O_ACCMODE | libc::O_CREAT | libc::O_TRUNC) as u64),
+		libc::O_RDONLY as u64,
+	)?;
+
+	let mut rules =
+		SYSCALLS.iter().map(|&syscall| (syscall, Vec::new())).collect::<BTreeMap<_, _>>();
+	rules.insert(libc::SYS_openat, vec![SeccompRule::new(vec![read_only])?]);
+
+	// Denied system calls fail gracefully instead of killing the worker outright
+	let filter = SeccompFilter::new(
+		rules,
+		SeccompAction::Errno(libc::EPERM as u32),
+		SeccompAction::Allow,
+		env::consts::ARCH.try_into()?,
+	)?;
+	let program = BpfProgram::try_from(filter)?;
+	seccompiler::apply_filter(&program)?;
+
+	info!(syscalls = SYSCALLS.len() + 1, "seccomp filter applied");
+	Ok(())
+}
@@ -1,3 +1,4 @@
+use crate::replay::ReplayGuard;
 use anyhow::{Context as _, Result};
 use axum::{
 	Router,
@@ -7,13 +8,36 @@ use axum::{
 	routing, serve,
 };
 use bytes::BytesMut;
-use core::{future, net::Ipv4Addr, time::Duration};
+use core::{future, net::Ipv4Addr, num::NonZeroUsize, time::Duration};
 use ed25519_dalek::{Signature, VerifyingKey};
 use futures_util::TryStreamExt as _;
-use std::{env, sync::Arc};
+use std::{
+	env,
+	path::Path,
+	sync::{
+		Arc,
+		atomic::{AtomicU64, Ordering},
+	},
+};
 use tokio::{net::TcpListener, runtime::Builder};
 use tracing::{error, info, instrument};
-use typscord_interaction::{InteractionHandler, InteractionResponse};
+use typscord_interaction::{Interaction, InteractionHandler, InteractionResponse, WorkerPool};
+
+/// Number of idle workers kept warm when `TYPSCORD_WORKER_POOL_SIZE` is not set.
+const DEFAULT_WORKER_POOL_SIZE: usize = 2;
+
+/// Number of renders that may run at once when `TYPSCORD_MAX_CONCURRENT_RENDERS` is not set.
+const DEFAULT_MAX_CONCURRENT_RENDERS: usize = 4;
+
+/// Interaction payloads are far smaller than this, so larger bodies are not even buffered. The
+/// largest ones resolve a message (with its embeds) for the message command.
+const MAX_BODY_SIZE: usize = 256 * 1024;
+
+/// Number of requests rejected for exceeding [`MAX_BODY_SIZE`], exposed at `/metrics`.
+static OVERSIZE_BODIES: AtomicU64 = AtomicU64::new(0);
+
+/// Maximum age (in seconds) of a signed request when `TYPSCORD_SIGNATURE_MAX_SKEW` is not set.
+const DEFAULT_SIGNATURE_MAX_SKEW: u64 = 60;
 
 #[instrument]
 pub fn main() -> Result<()> {
@@ -40,6 +64,57 @@ pub fn main() -> Result<()> {
 			.context("DISCORD_PUBLIC_KEY must be valid point under ZIP-215 rules")?
 	};
 
+	let typscord_signature_max_skew = match env::var("TYPSCORD_SIGNATURE_MAX_SKEW") {
+		Ok(secs) => secs.parse().context("TYPSCORD_SIGNATURE_MAX_SKEW must be a valid duration")?,
+		Err(_) => DEFAULT_SIGNATURE_MAX_SKEW,
+	};
+
+	let typscord_worker_pool_size = match env::var("TYPSCORD_WORKER_POOL_SIZE") {
+		Ok(size) => size.parse().context("TYPSCORD_WORKER_POOL_SIZE must be a valid count")?,
+		Err(_) => DEFAULT_WORKER_POOL_SIZE,
+	};
+
+	let typscord_worker_recycle_after = env::var("TYPSCORD_WORKER_RECYCLE_AFTER")
+		.ok()
+		.map(|millis| millis.parse().map(Duration::from_millis))
+		.transpose()
+		.context("TYPSCORD_WORKER_RECYCLE_AFTER must be a valid duration")?;
+
+	// The fonts themselves are only loaded by the workers
+	let font_families =
+		typscord_world::font_families(env::var_os("TYPSCORD_FONT_PATH").as_deref().map(Path::new))
+			.context("TYPSCORD_FONT_PATH must be a readable directory")?;
+	info!(count = font_families.len(), "font families found");
+
+	// The workers parse the allowlist again, so a typo must not get that far
+	let typscord_package_allowlist = typscord_world::parse_allowlist(
+		&env::var("TYPSCORD_PACKAGE_ALLOWLIST").unwrap_or_default(),
+	)
+	.map_err(anyhow::Error::msg)
+	.context("TYPSCORD_PACKAGE_ALLOWLIST must be a comma-separated list of packages")?;
+	info!(count = typscord_package_allowlist.len(), "approved packages found");
+
+	let typscord_max_concurrent_renders = match env::var("TYPSCORD_MAX_CONCURRENT_RENDERS") {
+		// Renders would otherwise wait in the queue until they expire
+		Ok(count) => count
+			.parse::<NonZeroUsize>()
+			.context("TYPSCORD_MAX_CONCURRENT_RENDERS must be a positive count")?
+			.get(),
+		Err(_) => DEFAULT_MAX_CONCURRENT_RENDERS,
+	};
+
+	// Every worker that may exist at once (running or idle) gets an equal share of the memory
+	let typscord_worker_memory_limit = match env::var("TYPSCORD_WORKER_MEMORY_LIMIT") {
+		Ok(mib) => Some(mib.parse().context("TYPSCORD_WORKER_MEMORY_LIMIT must be a valid size")?),
+		#[cfg(target_os = "linux")]
+		Err(_) => Some(crate::sandbox::memory_limit(
+			typscord_max_concurrent_renders + typscord_worker_pool_size,
+		)?),
+		#[cfg(not(target_os = "linux"))]
+		Err(_) => None,
+	};
+	info!(memory_limit = typscord_worker_memory_limit, "worker memory limit chosen");
+
 	let exe_path = env::current_exe()?.into_boxed_path();
 	info!(exe = %exe_path.display(), "executable path found");
 
@@ -50,15 +125,28 @@ pub fn main() -> Result<()> {
 			info!(%address, "listening on local address");
 		}
 
+		let workers = WorkerPool::new(
+			exe_path,
+			typscord_worker_pool_size,
+			typscord_worker_recycle_after,
+			typscord_worker_memory_limit,
+		)?;
+
 		let app = Router::new()
 			.route("/", routing::get(handle_health_check))
+			.route("/metrics", routing::get(handle_metrics))
 			.route("/discord/interaction", routing::post(handle_discord_interaction))
 			.with_state(KeyState {
 				public_key: Arc::new(public_key),
+				replay_guard: Arc::new(ReplayGuard::new(Duration::from_secs(
+					typscord_signature_max_skew,
+				))),
 				interaction_handler: Arc::new(InteractionHandler::new(
 					Duration::from_millis(typscord_compilation_timeout),
-					exe_path,
+					workers,
 					discord_bot_token,
+					font_families,
+					typscord_max_concurrent_renders,
 				)),
 			});
 
@@ -73,15 +161,25 @@ fn handle_health_check() -> future::Ready<StatusCode> {
 	future::ready(StatusCode::OK)
 }
 
+/// Reports the counters in the Prometheus text format.
+#[instrument]
+fn handle_metrics() -> future::Ready<String> {
+	let oversize_bodies = OVERSIZE_BODIES.load(Ordering::Relaxed);
+	future::ready(format!(
+		"# TYPE typscord_oversize_bodies_total counter\ntypscord_oversize_bodies_total {oversize_bodies}\n"
+	))
+}
+
 #[derive(Clone)]
 struct KeyState {
 	public_key: Arc<VerifyingKey>,
+	replay_guard: Arc<ReplayGuard>,
 	interaction_handler: Arc<InteractionHandler>,
 }
 
 #[instrument(skip_all)]
 async fn handle_discord_interaction(
-	State(KeyState { public_key, interaction_handler }): State<KeyState>,
+	State(KeyState { public_key, replay_guard, interaction_handler }): State<KeyState>,
 	request: Request,
 ) -> Result<Json<InteractionResponse>, StatusCode> {
 	let (Parts { headers, .. }, body) = request.into_parts();
@@ -98,20 +196,37 @@ async fn handle_discord_interaction(
 		StatusCode::BAD_REQUEST
 	})?;
 
+	// Replays of captured requests carry the original timestamp, which eventually goes stale
+	let signed_at = timestamp
+		.to_str()
+		.ok()
+		.and_then(|timestamp| timestamp.parse().ok())
+		.ok_or(StatusCode::UNAUTHORIZED)?;
+	if !replay_guard.is_fresh(signed_at) {
+		error!(signed_at, "stale interaction request");
+		return Err(StatusCode::UNAUTHORIZED);
+	}
+
 	let message = BytesMut::from(timestamp.as_bytes());
 	let start = message.len();
 
 	let message = body
 		.into_data_stream()
+		.map_err(|error| {
+			error!(?error);
+			StatusCode::BAD_REQUEST
+		})
 		.try_fold(message, |mut message, chunk| {
+			// The timestamp prefix does not count toward the limit
+			if message.len() - start + chunk.len() > MAX_BODY_SIZE {
+				let total = OVERSIZE_BODIES.fetch_add(1, Ordering::Relaxed) + 1;
+				error!(total, "request body too large");
+				return future::ready(Err(StatusCode::PAYLOAD_TOO_LARGE));
+			}
 			message.extend_from_slice(&chunk);
 			future::ready(Ok(message))
 		})
-		.await
-		.map_err(|error| {
-			error!(?error);
-			StatusCode::BAD_REQUEST
-		})?;
+		.await?;
 
 	public_key.verify_strict(&message, &signature).map_err(|error| {
 		error!(?error);
@@ -119,10 +234,16 @@ async fn handle_discord_interaction(
 	})?;
 
 	let json = message.get(start..).ok_or(StatusCode::BAD_REQUEST)?;
-	let interaction = serde_json::from_slice(json).map_err(|error| {
+	let interaction: Interaction = serde_json::from_slice(json).map_err(|error| {
 		error!(?error);
 		StatusCode::BAD_REQUEST
 	})?;
 
+	// Only verified requests are remembered so that forged ones cannot block real interactions
+	if !replay_guard.witness(interaction.id.get(), signed_at) {
+		error!(interaction_id = ?interaction.id, "replayed interaction request");
+		return Err(StatusCode::UNAUTHORIZED);
+	}
+
 	Ok(Json(interaction_handler.handle(interaction)))
 }
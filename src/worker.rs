@@ -1,68 +1,263 @@
-use std::io::{self, Read as _, Write as _};
-use tracing::{error, info, instrument};
-use typscord_world::{Render, SourceDiagnostic, Warned, World};
+use anyhow::Result;
+use std::{
+	env, io,
+	path::{Path, PathBuf},
+	time::Instant,
+};
+use tracing::{error, info, instrument, warn};
+use typscord_protocol::{Diagnostic, File, Render, Request, Response, Timing, Trace};
+use typscord_world::{
+	PackageStore, PagedDocument, Pages, Project, ProjectError, RenderOptions, SourceDiagnostic,
+	UtcOffset, Warned, World, export,
+};
 
 /// Discord only allows up to 25 fields per embed.
 pub const MAX_DIAGNOSTIC_COUNT: usize = 25;
 
+/// Deeply nested calls would otherwise overflow the embed field.
+const MAX_TRACE_DEPTH: usize = 3;
+
+/// Discord only allows up to 10 attachments per message.
+pub const MAX_PAGE_COUNT: usize = 10;
+
+/// 8 MiB is Discord's upload limit, which applies to all attachments of a message combined.
+pub const MAX_MESSAGE_SIZE: usize = 1024 * 1024 * 8;
+
+/// Raster outputs that exceed [`MAX_MESSAGE_SIZE`] are re-exported at these percentages of the
+/// original resolution until they fit.
+const DOWNSCALE_PERCENTAGES: [usize; 3] = [75, 50, 25];
+
 #[instrument]
-pub fn main() -> io::Result<()> {
-	let mut content = String::new();
+pub fn main() -> Result<()> {
+	#[cfg(target_os = "linux")]
+	crate::sandbox::apply()?;
+	#[cfg(not(target_os = "linux"))]
+	warn!("the worker sandbox is only available on Linux");
 
-	{
-		let size = io::stdin().read_to_string(&mut content)?;
-		info!(%size, "read content from stdin");
+	// Pooled workers do this while they are still idle
+	let start = Instant::now();
+	if let Some(dir) = env::var_os("TYPSCORD_FONT_PATH") {
+		typscord_world::load_fonts(Path::new(&dir))?;
 	}
+	typscord_world::warm_up();
+	let packages = package_store()?;
+	info!(elapsed = ?start.elapsed(), "worker warmed up");
 
-	let world = World::from_single_source(content);
-	let Warned { output, mut warnings } = world.render();
+	let Request {
+		files,
+		entry,
+		format,
+		pages,
+		pixel_per_pt,
+		inputs,
+		timestamp,
+		utc_offset,
+		preamble,
+		code,
+	} = typscord_protocol::read(&mut io::stdin().lock())?;
+	info!(
+		files = files.len(),
+		?entry,
+		?format,
+		?pages,
+		pixel_per_pt,
+		inputs = inputs.len(),
+		?timestamp,
+		utc_offset,
+		size = code.len(),
+		"read request"
+	);
 
-	let warning_count = warnings.len();
-	info!(warnings = warning_count, "document render complete");
+	let pages = match pages {
+		typscord_protocol::Pages::Merged => Pages::Merged,
+		typscord_protocol::Pages::Split => Pages::Split(MAX_PAGE_COUNT),
+	};
 
-	// Only show the most important warnings
-	warnings.truncate(MAX_DIAGNOSTIC_COUNT);
+	// The preamble only lets the document break into pages when they are split anyway
+	let mut inputs = inputs;
+	if let Pages::Split(_) = pages {
+		inputs.push(("split-pages".into(), "true".into()));
+	}
 
-	let mut stdout = io::stdout().lock();
+	let utc_offset = UtcOffset::from_whole_seconds(utc_offset)?;
+	let response = match build_world(files, entry.as_deref(), &preamble, &code) {
+		Ok(world) => {
+			let world = match packages {
+				Some(packages) => world.with_package_store(packages),
+				None => world,
+			};
+			let world = world.with_inputs(inputs).with_clock(timestamp, utc_offset);
+			render(&world, RenderOptions { format, pages, pixel_per_pt })
+		}
+		Err(error) => {
+			error!(%error, "invalid project");
+			Response {
+				warnings: Vec::new(),
+				result: Err(vec![Diagnostic {
+					message: error.to_string(),
+					hints: vec!["Check the attached files and the entry point.".into()],
+					location: None,
+					trace: Vec::new(),
+				}]),
+				timing: Timing::default(),
+			}
+		}
+	};
 
-	stdout.write_all(&warning_count.to_be_bytes())?; // warnings
-	for SourceDiagnostic { message, hints, .. } in warnings {
-		writeln!(stdout, "{message}")?; // name
-		let hint = hints.first().map(AsRef::as_ref).unwrap_or("No hints provided.");
-		writeln!(stdout, "{hint}")?; // value
-	}
+	typscord_protocol::write(&mut io::stdout().lock(), &response)?;
+	Ok(())
+}
+
+fn render(world: &World, options: RenderOptions) -> Response {
+	let start = Instant::now();
+	let Warned { output, warnings } = world.compile::<PagedDocument>();
+	let compile = start.elapsed();
+	info!(warnings = warnings.len(), ?compile, "document compilation complete");
+
+	let start = Instant::now();
+	let result = output.and_then(|document| {
+		let files = export(&document, options)?;
+		Ok((document, files))
+	});
+
+	let result = match result {
+		Ok((document, mut files)) => {
+			let page_count = document.pages.len();
+			let format = options.format;
+			info!(pages = page_count, files = files.len(), ?format, "document rendered");
 
-	match output {
-		Ok(Render { buffer, .. }) => {
-			let buffer_size = buffer.len(); // image
-			info!(size = buffer_size, "image rendered");
+			let fits = |files: &[Vec<u8>]| total_size(files) < MAX_MESSAGE_SIZE;
 
-			if buffer_size >= 1024 * 1024 * 8 {
-				// 8 MiB is Discord's limit.
-				error!(size = buffer_size, "maximum file size exceeded");
-				return Err(io::ErrorKind::FileTooLarge.into());
+			let mut scale = 100;
+			if format.is_raster() {
+				for percentage in DOWNSCALE_PERCENTAGES {
+					if fits(&files) {
+						break;
+					}
+
+					let pixel_per_pt = options.pixel_per_pt * percentage as f32 / 100.;
+					warn!(pixel_per_pt, "maximum message size exceeded, downscaling");
+
+					files = export(&document, RenderOptions { pixel_per_pt, ..options })
+						.expect("raster exports must be infallible");
+					scale = percentage;
+				}
 			}
 
-			// communicate that there is no error
-			stdout.write_all(&0usize.to_be_bytes())?;
-			stdout.write_all(&buffer)?;
-		}
-		Err(mut errors) => {
-			let error_count = errors.len();
-			info!(errors = error_count, "errors encountered");
-
-			// Only show the most important errors
-			errors.truncate(MAX_DIAGNOSTIC_COUNT);
-
-			stdout.write_all(&error_count.to_be_bytes())?; // errors
-			for SourceDiagnostic { message, hints, .. } in errors {
-				writeln!(stdout, "{message}")?; // name
-				let hint = hints.first().map(AsRef::as_ref).unwrap_or("No hints provided.");
-				writeln!(stdout, "{hint}")?; // value
+			// Split pages that still do not fit together are dropped from the end instead
+			if !fits(&files) && files.len() > 1 {
+				let mut size = 0;
+				let kept = files
+					.iter()
+					.take_while(|file| {
+						size += file.len();
+						size < MAX_MESSAGE_SIZE
+					})
+					.count();
+				if kept > 0 {
+					warn!(kept, dropped = files.len() - kept, "maximum message size exceeded");
+					files.truncate(kept);
+				}
 			}
+
+			let size = total_size(&files);
+			if size >= MAX_MESSAGE_SIZE {
+				error!(size, "maximum message size exceeded");
+				let hint = if format.is_raster() {
+					"Even the lowest resolution is too large. Try attaching each page separately."
+				} else {
+					"Vector formats cannot be downscaled. Try WebP or PNG instead."
+				};
+				Err(vec![Diagnostic {
+					message: format!(
+						"The rendered output exceeds Discord's {} MiB upload limit.",
+						MAX_MESSAGE_SIZE >> 20
+					),
+					hints: vec![hint.into()],
+					location: None,
+					trace: Vec::new(),
+				}])
+			} else {
+				Ok(Render { page_count, scale, files })
+			}
+		}
+		Err(errors) => {
+			info!(errors = errors.len(), "errors encountered");
+			Err(diagnostics(world, errors))
+		}
+	};
+
+	let export = start.elapsed();
+	info!(?export, "document export complete");
+
+	let warnings = diagnostics(world, warnings);
+	Response { warnings, result, timing: Timing { compile, export } }
+}
+
+fn total_size(files: &[Vec<u8>]) -> usize {
+	files.iter().map(Vec::len).sum()
+}
+
+/// Only the most important diagnostics are kept. Each one is resolved to where it occurred in the
+/// user's code.
+fn diagnostics(
+	world: &World,
+	diagnostics: impl IntoIterator<Item = SourceDiagnostic>,
+) -> Vec<Diagnostic> {
+	diagnostics
+		.into_iter()
+		.take(MAX_DIAGNOSTIC_COUNT)
+		.map(|SourceDiagnostic { span, message, trace, hints, .. }| Diagnostic {
+			message: message.into(),
+			hints: hints.into_iter().map(Into::into).collect(),
+			location: world.locate(span),
+			trace: trace
+				.into_iter()
+				.take(MAX_TRACE_DEPTH)
+				.map(|point| Trace {
+					message: point.v.to_string(),
+					location: world.locate(point.span),
+				})
+				.collect(),
+		})
+		.collect()
+}
+
+/// Attached `.zip` archives are extracted into the project root. All other attachments are added
+/// to the project as is.
+fn build_world(
+	files: Vec<File>,
+	entry: Option<&str>,
+	preamble: &str,
+	code: &str,
+) -> Result<World, ProjectError> {
+	let mut project = Project::default();
+	for File { name, bytes } in files {
+		if name.ends_with(".zip") {
+			project.extract_zip(&bytes)?;
+		} else {
+			project.insert(&name, bytes)?;
 		}
 	}
 
-	drop(stdout);
-	Ok(())
+	match entry {
+		None => Ok(World::from_source_and_project(preamble, code, project)),
+		Some(entry) => World::from_project(project, entry, preamble),
+	}
+}
+
+/// Packages are only available when `TYPSCORD_PACKAGE_PATH` is set. Only the comma-separated
+/// package specifications in `TYPSCORD_PACKAGE_ALLOWLIST` (e.g., `@preview/cetz:0.4.2`) may be
+/// imported from the store. The server has already validated the allowlist at startup.
+fn package_store() -> Result<Option<PackageStore>> {
+	let Some(root) = env::var_os("TYPSCORD_PACKAGE_PATH") else {
+		return Ok(None);
+	};
+
+	let allowlist = env::var("TYPSCORD_PACKAGE_ALLOWLIST").unwrap_or_default();
+	let allowlist = typscord_world::parse_allowlist(&allowlist).map_err(anyhow::Error::msg)?;
+
+	let root = PathBuf::from(root).into_boxed_path();
+	info!(root = %root.display(), packages = allowlist.len(), "package store configured");
+	Ok(Some(PackageStore::new(root, allowlist)))
 }